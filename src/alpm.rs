@@ -6,7 +6,9 @@
 
 //! Utilities for ALPM.
 
-use alpm::LogLevel;
+use std::collections::BTreeMap;
+
+use alpm::{Alpm, Db, LogLevel, Package, PackageReason, PackageValidation, Pkg, Ver};
 use tracing::Level;
 
 /// Tracing log callback for [`alpm::Alpm::set_log_cb`].
@@ -21,3 +23,248 @@ pub fn tracing_log_cb(level: alpm::LogLevel, message: &str, _: &mut ()) {
         _ => tracing::event!(target: "alpm", Level::TRACE, "{}", message),
     }
 }
+
+/// Official Arch Linux repositories, which have package pages on archlinux.org.
+const OFFICIAL_REPOSITORIES: &[&str] = &[
+    "core",
+    "core-testing",
+    "extra",
+    "extra-testing",
+    "multilib",
+    "multilib-testing",
+    "gnome-unstable",
+    "kde-unstable",
+];
+
+/// Repositories of packages.
+///
+/// Resolve the sync databases, i.e. repositories, of installed packages.
+#[derive(Debug)]
+pub struct Repositories<'a> {
+    syncdbs: Vec<&'a Db>,
+    arch: Option<String>,
+}
+
+impl<'a> Repositories<'a> {
+    /// Create repositories from all sync databases of `alpm`.
+    ///
+    /// `arch` denotes the architecture for package URLs; if `None` use the
+    /// first architecture configured in `alpm`.
+    #[must_use]
+    pub fn new(alpm: &'a Alpm, arch: Option<&str>) -> Self {
+        let arch = arch
+            .or_else(|| alpm.architectures().iter().next())
+            .filter(|arch| *arch != "auto")
+            .map(ToOwned::to_owned);
+        Self {
+            syncdbs: alpm.syncdbs().iter().collect(),
+            arch,
+        }
+    }
+
+    /// Find the sync database which contains a package.
+    ///
+    /// Return the first sync database which has a package of the same name as
+    /// `package`, or `None` if `package` is a foreign package, which is not
+    /// contained in any sync database.
+    #[must_use]
+    pub fn find_syncdb(&self, package: &Pkg) -> Option<&'a Db> {
+        self.syncdbs
+            .iter()
+            .find(|db| db.pkg(package.name()).is_ok())
+            .copied()
+    }
+
+    /// Whether a package is foreign, i.e. not contained in any sync database.
+    #[must_use]
+    pub fn is_foreign(&self, package: &Pkg) -> bool {
+        self.find_syncdb(package).is_none()
+    }
+
+    /// Find a newer version of a package.
+    ///
+    /// Return the package of the same name in the first sync database which
+    /// has it, if its version is newer than the version of `package`.
+    #[must_use]
+    pub fn find_upgrade(&self, package: &Pkg) -> Option<&'a Package> {
+        self.find_syncdb(package)
+            .and_then(|db| db.pkg(package.name()).ok())
+            .filter(|candidate| package.version() < candidate.version())
+    }
+
+    /// Get the URL of the Arch Linux package page of a package.
+    ///
+    /// Return `None` if `package` is not in an official Arch Linux repository.
+    ///
+    /// Architecture independent packages always use the `any` architecture;
+    /// all other packages use the architecture of these repositories, or the
+    /// architecture of `package` if there is none.
+    #[must_use]
+    pub fn package_url(&self, package: &Pkg) -> Option<String> {
+        let repository = self
+            .find_syncdb(package)
+            .map(Db::name)
+            .filter(|name| OFFICIAL_REPOSITORIES.contains(name))?;
+        let arch = match package.arch() {
+            Some("any") => "any",
+            arch => self.arch.as_deref().or(arch)?,
+        };
+        Some(format!(
+            "https://archlinux.org/packages/{repository}/{arch}/{}/",
+            package.name()
+        ))
+    }
+}
+
+/// Split a package version into its components.
+///
+/// Split a version like `2:1.0-3` into its epoch `2`, its `pkgver` `1.0` and
+/// its `pkgrel` `3`.  Return `None` for the epoch and the `pkgrel` if the
+/// version has none.
+#[must_use]
+pub fn version_components(version: &str) -> (Option<&str>, &str, Option<&str>) {
+    let (epoch, rest) = match version.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => (Some(epoch), rest),
+        _ => (None, version),
+    };
+    match rest.rsplit_once('-') {
+        Some((pkgver, pkgrel)) => (epoch, pkgver, Some(pkgrel)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Find a package by name, ignoring case.
+///
+/// Return the package in `db` with exactly the given `name`, or the first
+/// package whose name equals `name` ignoring ASCII case.
+#[must_use]
+pub fn find_package_ignore_case<'a>(db: &'a Db, name: &str) -> Option<&'a Package> {
+    db.pkg(name).ok().or_else(|| {
+        db.pkgs()
+            .into_iter()
+            .find(|package| package.name().eq_ignore_ascii_case(name))
+    })
+}
+
+/// Find packages which provide a name.
+///
+/// `provide` is either a plain name, e.g. `sh`, or a name with an exact
+/// version, e.g. a soname like `libfoo.so=1-64`.  Return all packages in `db`
+/// with a matching entry in their `provides`; if `provide` has a version only
+/// match provides with the same version.
+#[must_use]
+pub fn find_providers<'a>(db: &'a Db, provide: &str) -> Vec<&'a Package> {
+    let (name, version) = match provide.split_once('=') {
+        Some((name, version)) => (name, Some(version)),
+        None => (provide, None),
+    };
+    db.pkgs()
+        .into_iter()
+        .filter(|package| {
+            package.provides().iter().any(|provided| {
+                provided.name() == name
+                    && version
+                        .is_none_or(|version| provided.version().map(Ver::as_str) == Some(version))
+            })
+        })
+        .collect()
+}
+
+/// Search packages in a database.
+///
+/// Return all packages in `db` whose name or description contains `keyword`,
+/// ignoring case.
+pub fn search_packages<'a>(db: &'a Db, keyword: &str) -> impl Iterator<Item = &'a Package> {
+    let keyword = keyword.to_lowercase();
+    db.pkgs().into_iter().filter(move |package| {
+        package.name().to_lowercase().contains(&keyword)
+            || package
+                .desc()
+                .is_some_and(|desc| desc.to_lowercase().contains(&keyword))
+    })
+}
+
+/// Find packages which pacman did not validate.
+///
+/// Return all packages in `db` which pacman installed without validating
+/// checksums or signatures, or whose validation is unknown.  Such packages
+/// were often installed manually, or are not signed.
+pub fn unvalidated_packages(db: &Db) -> impl Iterator<Item = &Package> {
+    db.pkgs().into_iter().filter(|package| {
+        let validation = package.validation();
+        validation.is_empty() || validation.contains(PackageValidation::NONE)
+    })
+}
+
+/// Get the year and month of a UNIX `timestamp`, in UTC.
+fn year_month(timestamp: i64) -> (i64, i64) {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = timestamp.div_euclid(86400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months starting at March, to put the leap day last
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month)
+}
+
+/// Count installed packages per month of installation.
+///
+/// Return the number of explicitly installed packages, and the number of
+/// packages installed as dependencies, for every year and month in which any
+/// package in `db` was installed, in UTC.  Skip packages without an install
+/// date.
+#[must_use]
+pub fn installs_per_month(db: &Db) -> BTreeMap<(i64, i64), (usize, usize)> {
+    let mut months = BTreeMap::<_, (usize, usize)>::new();
+    for pkg in db.pkgs() {
+        if let Some(date) = pkg.install_date() {
+            let (explicit, depend) = months.entry(year_month(date)).or_default();
+            match pkg.reason() {
+                PackageReason::Explicit => *explicit += 1,
+                PackageReason::Depend => *depend += 1,
+            }
+        }
+    }
+    months
+}
+
+#[cfg(test)]
+mod tests {
+    use super::year_month;
+
+    #[test]
+    fn year_month_epoch() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month(-1), (1969, 12));
+    }
+
+    #[test]
+    fn year_month_leap_days() {
+        // 2000-02-29T12:00:00Z
+        assert_eq!(year_month(951_825_600), (2000, 2));
+        // 2000-03-01T00:00:00Z
+        assert_eq!(year_month(951_868_800), (2000, 3));
+        // 2100 is not a leap year: 2100-02-28T23:59:59Z and 2100-03-01T00:00:00Z
+        assert_eq!(year_month(4_107_542_399), (2100, 2));
+        assert_eq!(year_month(4_107_542_400), (2100, 3));
+    }
+
+    #[test]
+    fn year_month_year_ends() {
+        // 1999-12-31T23:59:59Z and 2000-01-01T00:00:00Z
+        assert_eq!(year_month(946_684_799), (1999, 12));
+        assert_eq!(year_month(946_684_800), (2000, 1));
+        // 2024-12-31T23:59:59Z and 2025-01-01T00:00:00Z
+        assert_eq!(year_month(1_735_689_599), (2024, 12));
+        assert_eq!(year_month(1_735_689_600), (2025, 1));
+    }
+}
@@ -4,9 +4,26 @@
 //
 // See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
 
-use clap::{Args, Parser, Subcommand};
+#![allow(
+    clippy::struct_excessive_bools,
+    reason = "Command line flags are independent bools"
+)]
 
-use crate::print::PrintOneLine;
+use std::{io::IsTerminal, os::fd::AsFd, path::PathBuf, process::Stdio, time::Duration};
+
+use alpm::{Alpm, Package, PackageReason};
+use anstream::ColorChoice;
+use clap::{
+    ArgAction, ArgMatches, Args, CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind,
+    parser::ValueSource,
+};
+use packit::{
+    alpm::Repositories,
+    graph::{DependencyEdge, PackageNode},
+};
+use tracing::level_filters::LevelFilter;
+
+use crate::print::{self, DotStyle, ListStyle, OneLineStyle, PrintOneLine};
 
 const AFTER_LONG_HELP: &str = "\
 Automatically print colored output if stdout is a TTY, unless overridden by
@@ -15,6 +32,12 @@ environment variables as follows:
 - If $NO_COLOR is set to a non-empty string, never print any colors.
 - If $CLICOLOR_FORCE is set to a non-empty string, always print colors even if
   stdout is not a TTY.
+- If $CLICOLOR is set to 0, do not print colors, unless $CLICOLOR_FORCE is set.
+
+The same applies to hyperlinks with --hyperlinks.
+
+Exit with status 1 if doctor found problems, with 2 if a package is not
+installed or arguments are invalid, and with 3 on all other errors.
 ";
 
 const LONG_VERSION: &str = concat!(
@@ -31,54 +54,1487 @@ const LONG_VERSION: &str = concat!(
 #[derive(Debug, Parser)]
 #[command(version, about, after_long_help = AFTER_LONG_HELP, long_version = LONG_VERSION)]
 pub struct CliArgs {
+    /// Log more information to stderr; repeat for even more.
+    ///
+    /// Log warnings by default, and informational, debug or trace messages
+    /// with one, two or three -v respectively.  `$RUST_LOG` takes precedence if
+    /// set.
+    #[clap(short = 'v', long = "verbose", action = ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Abort if the command takes longer than this many seconds.
+    #[clap(long, value_name = "SECS", global = true)]
+    pub timeout: Option<u64>,
+    /// Print output for scripts.
+    ///
+    /// Never print colors or hyperlinks, and print lists of packages as
+    /// tab-separated values.
+    #[clap(long, global = true)]
+    pub machine_readable: bool,
+    /// Print a summary as a single line of JSON to stderr after the command.
+    ///
+    /// The summary has the name of the command, the number of installed
+    /// packages, the number of lines printed to stdout, e.g. the number of
+    /// packages listed, the exit code, and the time the command took in
+    /// milliseconds.
+    #[clap(long, global = true)]
+    pub summary_json: bool,
+    /// Describe the output on stderr before printing it.
+    ///
+    /// Print one line which explains what the given options print, e.g.
+    /// whether packages are printed with versions, as aligned text or
+    /// tab-separated values, and with colors.
+    #[clap(long, global = true)]
+    pub explain_output: bool,
     #[command(subcommand)]
     pub command: Command,
 }
 
+impl CliArgs {
+    pub fn log_level(&self) -> LevelFilter {
+        match self.verbose {
+            0 => LevelFilter::WARN,
+            1 => LevelFilter::INFO,
+            2 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        }
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout.map(Duration::from_secs)
+    }
+
+    /// Describe the output of `command` with the given options.
+    pub fn describe_output(&self, command: &str) -> String {
+        if let Some(graph_options) = self.command.graph_options()
+            && graph_options.dot
+        {
+            let target = graph_options
+                .output_graph
+                .as_ref()
+                .map_or_else(|| "stdout".to_string(), |path| path.display().to_string());
+            return format!("{command}: printing a dot graph to {target}");
+        }
+        let colors = if anstream::AutoStream::choice(&std::io::stdout()) == ColorChoice::Never {
+            "without colors"
+        } else {
+            "with colors"
+        };
+        match self.command.display_options() {
+            Some(options) => format!("{command}: printing {}, {colors}", options.describe()),
+            None => format!("{command}: printing a command-specific report, {colors}"),
+        }
+    }
+
+    /// Check that the command in `matches` supports machine-readable output.
+    ///
+    /// `--machine-readable` implies `--format tsv`, so reject all options
+    /// given on the command line which conflict with `--format`.
+    pub fn check_machine_readable(matches: &ArgMatches) -> Result<(), clap::Error> {
+        let mut command = Self::command();
+        command.build();
+        let Some((name, matches)) = matches.subcommand() else {
+            return Ok(());
+        };
+        let Some(subcommand) = command.find_subcommand(name) else {
+            return Ok(());
+        };
+        let Some(format) = subcommand
+            .get_arguments()
+            .find(|arg| arg.get_id() == "format")
+        else {
+            return Ok(());
+        };
+        let conflicts_with_format = |arg: &clap::Arg| {
+            arg.get_id() != format.get_id()
+                && (subcommand
+                    .get_arg_conflicts_with(arg)
+                    .iter()
+                    .any(|other| other.get_id() == format.get_id())
+                    || subcommand
+                        .get_arg_conflicts_with(format)
+                        .iter()
+                        .any(|other| other.get_id() == arg.get_id()))
+        };
+        let conflict = subcommand
+            .get_arguments()
+            .filter(|arg| conflicts_with_format(arg))
+            .find(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+            .map(|arg| {
+                arg.get_long()
+                    .map_or_else(|| arg.get_id().to_string(), |long| format!("--{long}"))
+            });
+        match conflict {
+            Some(arg) => Err(command.error(
+                ErrorKind::ArgumentConflict,
+                format!("--machine-readable prints TSV, which cannot be used with {arg}"),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Switch the display options of the command to machine-readable output.
+    pub fn apply_machine_readable(&mut self) {
+        if let Some(options) = self.command.display_options_mut() {
+            options.hyperlinks = false;
+            options.format = Format::Tsv;
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     Dependents(Dependents),
     Orphans(Orphans),
+    Search(Search),
+    List(List),
+    Optdepends(Optdepends),
+    WouldOrphan(WouldOrphan),
+    Missing(Missing),
+    Redundant(Redundant),
+    Compare(Compare),
+    Validate(Validate),
+    ReinstallCandidates(ReinstallCandidates),
+    Soname(Soname),
+    Disk(Disk),
+    Distances(Distances),
+    Central(Central),
+    Paths(Paths),
+    LongestChain(LongestChain),
+    Providers(Providers),
+    Stats(Stats),
+    /// Show how many packages were installed per month.
+    ///
+    /// Count explicitly installed packages and packages installed as
+    /// dependencies by the month of their installation, and draw a histogram.
+    /// pacman resets the install date when upgrading a package, so this is the
+    /// month of the last installation or upgrade.
+    History,
+    /// Check installed packages for problems.
+    ///
+    /// Report unsatisfied dependencies, including dependencies on the wrong
+    /// version of a package, as errors, and dependency cycles and foreign
+    /// orphans as warnings on stderr.  Exit with status 1 if there are any
+    /// errors.
+    Doctor,
+    /// Dump all edges of the dependency graph, for debugging.
+    #[command(hide = true)]
+    ExplainEdges,
     #[cfg(feature = "completions")]
     Completions(Completions),
 }
 
+impl Command {
+    fn display_options(&self) -> Option<&DisplayOptions> {
+        match self {
+            Command::Dependents(Dependents { graph_options, .. })
+            | Command::Orphans(Orphans { graph_options, .. })
+            | Command::List(List { graph_options, .. })
+            | Command::Paths(Paths { graph_options, .. })
+            | Command::Providers(Providers { graph_options, .. }) => {
+                Some(&graph_options.display_options)
+            }
+            Command::Search(Search {
+                display_options, ..
+            })
+            | Command::Optdepends(Optdepends {
+                display_options, ..
+            })
+            | Command::WouldOrphan(WouldOrphan {
+                display_options, ..
+            })
+            | Command::Missing(Missing {
+                display_options, ..
+            })
+            | Command::Redundant(Redundant {
+                display_options, ..
+            })
+            | Command::Compare(Compare {
+                display_options, ..
+            })
+            | Command::Validate(Validate { display_options })
+            | Command::ReinstallCandidates(ReinstallCandidates {
+                display_options, ..
+            })
+            | Command::Soname(Soname {
+                display_options, ..
+            })
+            | Command::Disk(Disk {
+                display_options, ..
+            }) => Some(display_options),
+            Command::ExplainEdges
+            | Command::Doctor
+            | Command::Distances(_)
+            | Command::LongestChain(_)
+            | Command::Central(_)
+            | Command::Stats(_)
+            | Command::History => None,
+            #[cfg(feature = "completions")]
+            Command::Completions(_) => None,
+        }
+    }
+
+    fn display_options_mut(&mut self) -> Option<&mut DisplayOptions> {
+        match self {
+            Command::Dependents(Dependents { graph_options, .. })
+            | Command::Orphans(Orphans { graph_options, .. })
+            | Command::List(List { graph_options, .. })
+            | Command::Paths(Paths { graph_options, .. })
+            | Command::Providers(Providers { graph_options, .. }) => {
+                Some(&mut graph_options.display_options)
+            }
+            Command::Search(Search {
+                display_options, ..
+            })
+            | Command::Optdepends(Optdepends {
+                display_options, ..
+            })
+            | Command::WouldOrphan(WouldOrphan {
+                display_options, ..
+            })
+            | Command::Missing(Missing {
+                display_options, ..
+            })
+            | Command::Redundant(Redundant {
+                display_options, ..
+            })
+            | Command::Compare(Compare {
+                display_options, ..
+            })
+            | Command::Validate(Validate { display_options })
+            | Command::ReinstallCandidates(ReinstallCandidates {
+                display_options, ..
+            })
+            | Command::Soname(Soname {
+                display_options, ..
+            })
+            | Command::Disk(Disk {
+                display_options, ..
+            }) => Some(display_options),
+            Command::ExplainEdges
+            | Command::Doctor
+            | Command::Distances(_)
+            | Command::LongestChain(_)
+            | Command::Central(_)
+            | Command::Stats(_)
+            | Command::History => None,
+            #[cfg(feature = "completions")]
+            Command::Completions(_) => None,
+        }
+    }
+
+    /// Get the graph options of the command, if any.
+    pub fn graph_options(&self) -> Option<&GraphOptions> {
+        match self {
+            Command::Dependents(Dependents { graph_options, .. })
+            | Command::Orphans(Orphans { graph_options, .. })
+            | Command::List(List { graph_options, .. })
+            | Command::Paths(Paths { graph_options, .. })
+            | Command::Providers(Providers { graph_options, .. }) => Some(graph_options),
+            _ => None,
+        }
+    }
+
+    /// Get the mutable graph options of the command, if any.
+    pub fn graph_options_mut(&mut self) -> Option<&mut GraphOptions> {
+        match self {
+            Command::Dependents(Dependents { graph_options, .. })
+            | Command::Orphans(Orphans { graph_options, .. })
+            | Command::List(List { graph_options, .. })
+            | Command::Paths(Paths { graph_options, .. })
+            | Command::Providers(Providers { graph_options, .. }) => Some(graph_options),
+            _ => None,
+        }
+    }
+
+    /// Whether to re-run the command whenever the local database changes.
+    pub fn watch(&self) -> bool {
+        match self {
+            Command::Dependents(Dependents { graph_options, .. })
+            | Command::Orphans(Orphans { graph_options, .. })
+            | Command::List(List { graph_options, .. })
+            | Command::Paths(Paths { graph_options, .. })
+            | Command::Providers(Providers { graph_options, .. }) => graph_options.watch,
+            _ => false,
+        }
+    }
+}
+
 /// List orphan packages.
 #[derive(Args, Debug)]
 pub struct Orphans {
+    /// Only list strict orphans.
+    ///
+    /// Strict orphans are orphans which no installed package optionally
+    /// requires, not even another orphan, i.e. which are not installed to
+    /// provide an optional feature of any package.  This is the strictest
+    /// definition of orphans; --report marks them as well.
+    #[clap(long, conflicts_with_all = ["ignore_optdepends", "follow_optdepends_from"])]
+    pub reverse_optional: bool,
+    /// Print a table of orphans with and without optional dependencies.
+    ///
+    /// List all packages which are orphans if optional dependencies are
+    /// ignored, mark which of these are also orphans if optional dependencies
+    /// are considered, and which are strict orphans, as in
+    /// --reverse-optional.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "ignore_optdepends",
+            "follow_optdepends_from",
+            "dot",
+            "format",
+        ]
+    )]
+    pub report: bool,
+    /// Show the number of optional dependents of each orphan.
+    ///
+    /// Orphans have no required dependents, but may still be optional
+    /// dependencies of other packages, in particular with
+    /// `--ignore-optdepends`.
+    #[clap(long, conflicts_with_all = ["report", "dot", "format"])]
+    pub count_optional_dependents: bool,
+    /// Group orphans by their repository.
+    ///
+    /// Foreign packages which are not in any sync database come last, in the
+    /// `foreign` group.
+    #[clap(long, conflicts_with_all = ["report", "dot", "format"])]
+    pub group_by_repo: bool,
+    /// Separate orphans from the dependencies they pull in.
+    ///
+    /// First list orphans which no other orphan depends on, i.e. what
+    /// `pacman -Qdt` shows, and then all orphans which are only installed as
+    /// dependencies of these, i.e. what `pacman -Rns` would remove in addition.
+    #[clap(
+        long = "depends-of-orphans",
+        conflicts_with_all = ["report", "dot", "group_by_repo", "format"]
+    )]
+    pub split_dependencies: bool,
+    /// Group orphans by the orphan which most likely pulled them in.
+    ///
+    /// Attribute every orphan to the nearest orphan which no other orphan
+    /// depends on, i.e. to what was most likely explicitly installed or
+    /// required by an explicitly installed package at some point, and list
+    /// orphans in groups named after these.
+    #[clap(
+        long,
+        conflicts_with_all = ["report", "dot", "group_by_repo", "split_dependencies", "format"]
+    )]
+    pub group_by_parent: bool,
+    /// Pick orphans to keep, and print a command to remove all others.
+    ///
+    /// List numbered orphans, and ask which to keep.  Then print the
+    /// `pacman -Rns` command to remove all other orphans; packit never removes
+    /// packages itself.  Print the plain list of orphans if stdin or stdout is
+    /// not a TTY.
+    #[cfg(feature = "interactive")]
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "report",
+            "dot",
+            "format",
+            "count_optional_dependents",
+            "group_by_repo",
+            "group_by_parent",
+            "split_dependencies",
+            "optional_only",
+            "annotate",
+            "pacman_compat",
+            "compare_pacman",
+            "hook",
+            "fast",
+        ]
+    )]
+    pub interactive: bool,
+    /// List packages which are only installed for optional dependencies.
+    ///
+    /// List all packages which are not explicitly installed, and which no
+    /// explicitly installed package requires, but which some explicitly
+    /// installed package optionally depends on, perhaps transitively.  These
+    /// packages provide optional features which may be worth reviewing.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "ignore_optdepends",
+            "follow_optdepends_from",
+            "dot",
+            "split_dependencies",
+        ]
+    )]
+    pub optional_only: bool,
+    /// Annotate orphans with and without optional dependencies in one list.
+    ///
+    /// List all packages which are orphans if optional dependencies are
+    /// ignored, and annotate them with `[R]`.  Annotate orphans which are also
+    /// orphans if optional dependencies are considered with `[R,O]`.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "ignore_optdepends",
+            "follow_optdepends_from",
+            "dot",
+            "format",
+            "count_optional_dependents",
+            "group_by_repo",
+            "group_by_parent",
+            "split_dependencies",
+            "optional_only",
+        ]
+    )]
+    pub annotate: bool,
+    /// Print orphans exactly like `pacman -Qdtq`.
+    ///
+    /// List the names of all packages installed as dependencies which no
+    /// installed package requires or optionally requires, one per line and
+    /// without colors.  This implies --quiet, and ignores all options which
+    /// filter or format orphans, to be a drop-in replacement in scripts.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "dot",
+            "format",
+            "optional_only",
+            "annotate",
+        ]
+    )]
+    pub pacman_compat: bool,
+    /// Compare orphans with what `pacman -Qdtq` lists.
+    ///
+    /// pacman only lists dependencies which no installed package requires or
+    /// optionally requires, so every package it lists is an orphan for packit
+    /// as well.  List all orphans which pacman misses, and explain why, i.e.
+    /// which other orphans require or optionally require them, e.g. in a
+    /// dependency cycle.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "count_optional_dependents",
+            "group_by_repo",
+            "group_by_parent",
+            "split_dependencies",
+            "optional_only",
+            "annotate",
+            "pacman_compat",
+            "exclude_optional_providers",
+            "hook",
+            "fast",
+            "roots",
+            "ignore_optdepends",
+            "follow_optdepends_from",
+            "dot",
+            "format",
+        ]
+    )]
+    pub compare_pacman: bool,
+    /// Do not keep packages which are only optionally required through a
+    /// name they provide.
+    ///
+    /// Ignore optional dependencies on provided names, e.g. a virtual package
+    /// like `java-runtime`, when looking for orphans, but still follow
+    /// optional dependencies on the real name of a package.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "ignore_optdepends",
+            "reverse_optional",
+            "report",
+            "optional_only",
+            "annotate",
+            "pacman_compat",
+            "fast",
+        ]
+    )]
+    pub exclude_optional_providers: bool,
+    /// Print a short report for pacman hooks.
+    ///
+    /// Print the number of orphans and their names, one per line and without
+    /// colors, and print nothing if there are no orphans.  See the README for
+    /// how to run this after every pacman transaction.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "count_optional_dependents",
+            "group_by_repo",
+            "group_by_parent",
+            "split_dependencies",
+            "optional_only",
+            "annotate",
+            "pacman_compat",
+            "fast",
+            "dot",
+            "format",
+        ]
+    )]
+    pub hook: bool,
+    /// Never list base packages as orphans.
+    ///
+    /// Exclude the `base` package and group, and all packages they require,
+    /// from the list of orphans.
+    #[clap(long, conflicts_with = "dot")]
+    pub no_base: bool,
+    /// Find orphans without building the dependency graph.
+    ///
+    /// Follow dependencies of explicitly installed packages directly in the
+    /// local database, which needs less time and memory on systems with many
+    /// packages.  This only supports plain lists of orphans.
+    #[clap(
+        long,
+        conflicts_with_all = [
+            "reverse_optional",
+            "report",
+            "count_optional_dependents",
+            "group_by_repo",
+            "group_by_parent",
+            "split_dependencies",
+            "optional_only",
+            "annotate",
+            "pacman_compat",
+            "no_base",
+            "roots",
+            "follow_optdepends_from",
+            "dot",
+        ]
+    )]
+    pub fast: bool,
     #[clap(flatten)]
     pub graph_options: GraphOptions,
 }
 
-/// List packages which depend on a package.
+/// List packages which depend on packages.
 #[derive(Args, Debug)]
 pub struct Dependents {
-    /// The package whose installation reason to find.
+    /// The packages whose installation reason to find.
+    ///
+    /// If there is no installed package of a name, use all packages which
+    /// provide the name instead, e.g. `libfoo.so=1-64` to find dependents of
+    /// whatever package provides this soname.
+    ///
+    /// Match package names ignoring case, unless --exact.
+    #[clap(required = true)]
+    pub packages: Vec<String>,
+    /// Match package names exactly, including case.
+    #[clap(long)]
+    pub exact: bool,
+    /// Do not warn about packages which are not installed.
+    ///
+    /// Still fail if none of the given packages is installed.
+    #[clap(long)]
+    pub quiet_errors: bool,
+    /// Only list packages which directly depend on the package.
+    #[clap(long)]
+    pub direct: bool,
+    /// Show the number of dependency paths from each dependent.
+    ///
+    /// Every dependent is listed once, but a dependent which reaches the
+    /// package in many different ways is more tightly bound to it.
+    #[clap(long, conflicts_with_all = ["direct", "dot", "format"])]
+    pub count_paths: bool,
+    /// Show provided names in the tree.
+    ///
+    /// Note dependents which depend on a name that the package provides, e.g.
+    /// a virtual package like `cron`, with `via cron`.
+    #[clap(long, requires = "tree")]
+    pub expand_provides_in_tree: bool,
+    /// Also list packages in sync databases which depend on the package.
+    ///
+    /// List packages which are not installed, but which directly depend on
+    /// the package, with the repository they come from, to see what would
+    /// depend on the package if installed.
+    #[clap(long, conflicts_with_all = ["dot", "format", "tree", "count_paths"])]
+    pub include_syncdbs: bool,
+    /// How to sort dependents.
+    ///
+    /// By default, sort dependents by name.
+    #[clap(long, value_enum, conflicts_with_all = ["dot", "count_paths"])]
+    pub sort: Option<SortDependents>,
+    /// Print dependents as a tree.
+    #[clap(long, conflicts_with_all = ["direct", "dot", "since_upgrade", "only_foreign", "only_native", "newer_version_available", "reason", "format", "prefix", "count_paths", "sort"])]
+    pub tree: bool,
+    /// Draw the tree with ASCII characters only.
+    #[clap(long, requires = "tree")]
+    pub ascii: bool,
+    /// Omit the queried package from the tree.
+    ///
+    /// Print its direct dependents as top-level entries instead.
+    #[clap(long, requires = "tree")]
+    pub no_root: bool,
+    /// Do not expand optional dependents in the tree.
+    ///
+    /// Print optional dependents as leaves, and only expand packages which
+    /// require their parent.
+    #[clap(long, requires = "tree", conflicts_with = "ignore_optdepends")]
+    pub optional_as_tree_leaves: bool,
+    /// Only expand the tree this many levels below the queried package.
+    ///
+    /// With 1 only print direct dependents, with 2 also their direct
+    /// dependents, etc.
+    #[clap(long, value_name = "N", requires = "tree")]
+    pub reverse_depth: Option<usize>,
+    #[clap(flatten)]
+    pub graph_options: GraphOptions,
+}
+
+/// Search installed packages by name and description.
+#[derive(Args, Debug)]
+pub struct Search {
+    /// The keyword to search for, ignoring case.
+    pub keyword: String,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List all installed packages.
+///
+/// Print the entire dependency graph of installed packages with --dot.
+#[derive(Args, Debug)]
+pub struct List {
+    /// Only list packages with more than N direct dependencies.
+    ///
+    /// Sort packages by their number of direct dependencies, most first, and
+    /// show the number after each package.
+    #[clap(long, value_name = "N", conflicts_with_all = ["dot", "format"])]
+    pub depends_count: Option<usize>,
+    #[clap(flatten)]
+    pub graph_options: GraphOptions,
+}
+
+/// List optional dependencies of explicitly installed packages.
+///
+/// Mark installed optional dependencies with [✓], and missing ones with [ ].
+#[derive(Args, Debug)]
+pub struct Optdepends {
+    /// Only list optional dependencies which are not installed.
+    #[clap(long)]
+    pub missing: bool,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List packages which would become orphans if a package is removed.
+///
+/// Simulate the removal of the package, and list all packages which are only
+/// installed because of the package, i.e. what `pacman -Rns` would remove in
+/// addition.  Warn about packages which require the package.  Never remove
+/// anything.
+#[derive(Args, Debug)]
+pub struct WouldOrphan {
+    /// The package to remove.
     pub package: String,
+    /// Ignore optional dependencies, like pacman does.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List required dependencies which are not installed.
+///
+/// List packages with required dependencies which are neither installed nor
+/// provided by any installed package, e.g. after a partial upgrade or after
+/// removing packages with `pacman -Rdd`.  Mark each missing dependency with
+/// [ ].
+#[derive(Args, Debug)]
+pub struct Missing {
+    /// Only check these packages; check all installed packages if empty.
+    pub packages: Vec<String>,
+    /// Check a package file which is not installed, e.g. a `.pkg.tar.zst`.
+    ///
+    /// Read the package from the file, and list all its required
+    /// dependencies, marked as installed or missing, to see what installing
+    /// the package would pull in.
+    #[clap(long, value_name = "PATH", conflicts_with = "packages")]
+    pub package_file: Option<PathBuf>,
+    /// Do not stop at the first package which is not installed.
+    ///
+    /// Warn about every package which is not installed, check all others, and
+    /// fail at the end if any package was not installed.
+    ///
+    /// Only `missing` has this option: `dependents` always warns about
+    /// packages which are not installed and continues, and `would-orphan` and
+    /// `paths` fail if any of their packages is not installed.
+    #[clap(long, conflicts_with = "package_file")]
+    pub keep_going: bool,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List explicitly installed packages which other packages require anyway.
+///
+/// These packages would remain installed as dependencies, so their explicit
+/// install reason is redundant; `pacman -D --asdeps` marks them as
+/// dependencies.
+#[derive(Args, Debug)]
+pub struct Redundant {
+    /// Also count optional dependencies.
+    #[clap(long)]
+    pub with_optdepends: bool,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// Compare the required dependencies of two packages.
+///
+/// List the transitive required dependencies unique to each package, and those
+/// both packages share.
+#[derive(Args, Debug)]
+pub struct Compare {
+    /// The first package.
+    pub first: String,
+    /// The second package.
+    pub second: String,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List packages which pacman did not validate.
+///
+/// List installed packages which pacman installed without checking checksums
+/// or signatures, or whose validation is unknown.  This often indicates
+/// manually installed or unsigned packages.
+#[derive(Args, Debug)]
+pub struct Validate {
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List packages which are candidates for reinstallation.
+///
+/// List installed packages which pacman did not validate, like `validate`
+/// does, as candidates to reinstall with `pacman -S` from a sync database,
+/// which validates packages.  packit only reports candidates, and never
+/// reinstalls packages.
+#[derive(Args, Debug)]
+pub struct ReinstallCandidates {
+    /// Only list packages which are available in a sync database.
+    #[clap(long)]
+    pub available: bool,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// List packages which provide a soname.
+///
+/// Match the soname exactly, including its version suffix, e.g.
+/// `libssl.so=3-64` only matches packages which provide exactly this version
+/// of `libssl.so`.  Without a version, e.g. `libssl.so`, match all versions.
+#[derive(Args, Debug)]
+pub struct Soname {
+    /// The soname, e.g. `libssl.so=3-64`.
+    #[clap(value_name = "SONAME", value_parser = parse_soname)]
+    pub soname: String,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// Show disk usage of installed packages.
+///
+/// List packages by their installed size, largest first.
+#[derive(Args, Debug)]
+pub struct Disk {
+    /// Sum up installed sizes instead of listing packages.
+    #[clap(long, value_enum, value_name = "WHAT")]
+    pub aggregate_by: Option<AggregateBy>,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
+}
+
+/// What to aggregate installed sizes by.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum AggregateBy {
+    /// Sum up sizes per repository, and of all foreign packages.
+    Repo,
+}
+
+/// List required dependencies of a package by distance.
+///
+/// Print the minimum number of required dependency edges from the seed package
+/// to each of its transitive dependencies, followed by a tab and the name of
+/// the dependency.  Sort by distance.
+#[derive(Args, Debug)]
+pub struct Distances {
+    /// The package to start from.
+    #[clap(long, value_name = "PKG")]
+    pub seed: String,
+}
+
+/// Rank packages by their degree centrality.
+///
+/// Print the degree of each package, i.e. the number of its dependencies and
+/// dependents, followed by a tab and the name of the package.  Packages with
+/// the highest degree come first; these are the structurally most important
+/// packages which most other packages are connected through.
+#[derive(Args, Debug)]
+pub struct Central {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Only print the N most central packages.
+    #[clap(long, value_name = "N")]
+    pub top: Option<usize>,
+}
+
+/// Show all dependency paths between two packages.
+///
+/// Print every route by which one package transitively depends on another,
+/// one path per line.  With --dot, print the subgraph of all packages and
+/// dependencies on any of these paths instead.
+#[derive(Args, Debug)]
+pub struct Paths {
+    /// The dependent package to start from.
+    #[clap(value_name = "FROM")]
+    pub from: String,
+    /// The dependency to find paths to.
+    #[clap(value_name = "TO")]
+    pub to: String,
+    /// Find at most N paths.
+    #[clap(long = "max-paths", value_name = "N", default_value_t = 100)]
+    pub limit: usize,
+    #[clap(flatten)]
+    pub graph_options: GraphOptions,
+}
+
+/// Show the longest dependency chain.
+///
+/// Print the longest path of dependencies between installed packages, i.e.
+/// the most deeply nested dependency, as a sequence of packages joined by
+/// arrows.  Ignore dependencies between packages in the same dependency
+/// cycle.
+#[derive(Args, Debug)]
+pub struct LongestChain {
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+}
+
+/// Show how a virtual name is provided and used.
+///
+/// List all packages which provide the name, e.g. a virtual package like
+/// `cron`, and all packages which depend on it.  With --dot, print a graph
+/// with the name in the center, dependents on its left and providers on its
+/// right.
+#[derive(Args, Debug)]
+pub struct Providers {
+    /// The provided name.
+    #[clap(value_name = "NAME")]
+    pub name: String,
     #[clap(flatten)]
     pub graph_options: GraphOptions,
 }
 
+/// Print statistics about the dependency graph.
+///
+/// Print the number of packages, explicitly installed packages, dependencies,
+/// required and optional edges, orphans, and dependency cycles, and the depth
+/// of the graph, i.e. the longest distance from an explicitly installed
+/// package to any of its dependencies.  Then print the packages with the most
+/// direct dependents.  Print each statistic on a line, followed by a tab and
+/// its value.
+#[derive(Args, Debug)]
+pub struct Stats {
+    /// Only analyse these packages and their dependencies.
+    ///
+    /// Compute all statistics over the subgraph of the given root packages
+    /// and all packages they transitively depend on, and measure the depth
+    /// from the roots.
+    #[clap(long, value_name = "PKG,...", value_delimiter = ',')]
+    pub roots: Vec<String>,
+    /// Ignore optional dependencies.
+    #[clap(long)]
+    pub ignore_optdepends: bool,
+    /// Print the N packages with the most direct dependents.
+    #[clap(long, value_name = "N", default_value_t = 5)]
+    pub top_dependents: usize,
+}
+
+/// Orders of dependents.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum SortDependents {
+    /// Sort dependents by name.
+    Name,
+    /// Sort dependents by their distance from the package, closest first.
+    ///
+    /// Sort dependents at the same distance by name.
+    Depth,
+}
+
+/// Parts of package versions to show.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum VersionFormat {
+    /// The full version, e.g. `2:1.0-3`.
+    Full,
+    /// The version without epoch, e.g. `1.0-3`.
+    NoEpoch,
+    /// Only the upstream version, without epoch and pkgrel, e.g. `1.0`.
+    Pkgver,
+}
+
+/// Install reasons of packages.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Reason {
+    /// Explicitly installed packages.
+    Explicit,
+    /// Packages installed as dependencies.
+    Dependency,
+}
+
+impl Reason {
+    pub fn package_reason(self) -> PackageReason {
+        match self {
+            Reason::Explicit => PackageReason::Explicit,
+            Reason::Dependency => PackageReason::Depend,
+        }
+    }
+}
+
+/// Kinds of edges to weight higher in dot graphs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum WeightBy {
+    /// Weight required dependencies higher than optional dependencies.
+    Required,
+}
+
+/// Graphviz styles for edges.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum EdgeStyle {
+    /// Solid lines.
+    Solid,
+    /// Dashed lines.
+    Dashed,
+    /// Dotted lines.
+    Dotted,
+    /// Thick solid lines.
+    Bold,
+}
+
+impl EdgeStyle {
+    /// The name of this style in dot.
+    fn dot_name(self) -> &'static str {
+        match self {
+            EdgeStyle::Solid => "solid",
+            EdgeStyle::Dashed => "dashed",
+            EdgeStyle::Dotted => "dotted",
+            EdgeStyle::Bold => "bold",
+        }
+    }
+}
+
+/// How to draw package nodes in dot graphs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum NodeStyle {
+    /// Label nodes with HTML-like labels.
+    Html,
+    /// Draw nodes as records of name, version, and installed size.
+    Record,
+}
+
+/// Formats to print packages in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One package per line, for humans.
+    ///
+    /// Align packages in columns if stdout is a TTY.
+    Text,
+    /// Tab-separated values.
+    Tsv,
+}
+
+#[derive(Debug, Args)]
+/// Options for displaying packages.
+pub struct DisplayOptions {
+    /// Show less information.
+    ///
+    /// Give twice to print dot graphs with plain name labels and no styling.
+    #[clap(short = 'q', long = "quiet", action = ArgAction::Count)]
+    pub quiet: u8,
+    /// Link package names to their Arch Linux package page.
+    ///
+    /// Use OSC 8 hyperlinks if colored output is enabled, by default if stdout
+    /// is a TTY.  Only packages in official Arch Linux repositories get a link.
+    #[clap(long)]
+    pub hyperlinks: bool,
+    /// Do not color package versions.
+    #[clap(long)]
+    pub no_color_version: bool,
+    /// Which part of package versions to show in text output.
+    #[clap(long, value_enum, default_value_t = VersionFormat::Full)]
+    pub version_format: VersionFormat,
+    /// Do not show the epoch of package versions.
+    ///
+    /// Shorthand for --version-format no-epoch.
+    #[clap(long, conflicts_with = "version_format")]
+    pub strip_epoch: bool,
+    /// Show the architecture of packages, e.g. `x86_64` or `any`.
+    #[clap(long)]
+    pub with_arch: bool,
+    /// Show how pacman validated packages, e.g. by signature.
+    #[clap(long)]
+    pub with_validation: bool,
+    /// Show the licenses of packages, separated by `/`.
+    #[clap(long)]
+    pub with_license: bool,
+    /// The format to print packages in.
+    ///
+    /// `tsv` prints tab-separated name, version, installed size in bytes, and
+    /// repository of each package, with a header line unless --quiet.
+    #[clap(long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// The architecture for links to Arch Linux package pages.
+    ///
+    /// Defaults to the architecture configured in pacman.conf.
+    #[clap(long)]
+    pub arch: Option<String>,
+}
+
+impl DisplayOptions {
+    /// Describe how these options print packages.
+    fn describe(&self) -> String {
+        let mut what = vec![if self.quiet > 0 {
+            "names"
+        } else {
+            "names and versions"
+        }];
+        if self.with_arch {
+            what.push("architectures");
+        }
+        if self.with_validation {
+            what.push("validation");
+        }
+        if self.with_license {
+            what.push("licenses");
+        }
+        let format = match self.format {
+            Format::Text if std::io::stdout().is_terminal() => "aligned text",
+            Format::Text => "text",
+            Format::Tsv if self.quiet > 0 => "tab-separated values without header",
+            Format::Tsv => "tab-separated values with header",
+        };
+        let links = if self.hyperlinks {
+            ", with hyperlinks"
+        } else {
+            ""
+        };
+        format!("{} as {format}{links}", what.join(", "))
+    }
+
+    fn print_one_line(&self) -> PrintOneLine {
+        if self.quiet > 0 {
+            PrintOneLine::NameOnly
+        } else {
+            PrintOneLine::WithVersion
+        }
+    }
+
+    pub fn repositories<'a>(&self, alpm: &'a Alpm) -> Repositories<'a> {
+        Repositories::new(alpm, self.arch.as_deref())
+    }
+
+    pub fn oneline_style<'a>(&self, repositories: &'a Repositories<'a>) -> OneLineStyle<'a> {
+        OneLineStyle {
+            what: self.print_one_line(),
+            color_version: !self.no_color_version,
+            version_format: match self.version_format {
+                VersionFormat::Full if self.strip_epoch => print::VersionFormat::NoEpoch,
+                VersionFormat::Full => print::VersionFormat::Full,
+                VersionFormat::NoEpoch => print::VersionFormat::NoEpoch,
+                VersionFormat::Pkgver => print::VersionFormat::Pkgver,
+            },
+            hyperlinks: (self.hyperlinks
+                && anstream::AutoStream::choice(&std::io::stdout()) != ColorChoice::Never)
+                .then_some(repositories),
+            with_arch: self.with_arch,
+            with_validation: self.with_validation,
+            with_license: self.with_license,
+            prefix: None,
+            columns: None,
+        }
+    }
+
+    pub fn list_style<'a>(&self, repositories: &'a Repositories<'a>) -> ListStyle<'a> {
+        match self.format {
+            Format::Text if std::io::stdout().is_terminal() => ListStyle::Aligned {
+                style: self.oneline_style(repositories),
+                max_width: terminal_width(),
+            },
+            Format::Text => ListStyle::OneLine(self.oneline_style(repositories)),
+            Format::Tsv => ListStyle::Tsv {
+                repositories,
+                header: self.quiet == 0,
+                with_arch: self.with_arch,
+                with_license: self.with_license,
+            },
+        }
+    }
+}
+
+/// Get the width of the terminal on stdout.
+///
+/// Ask `stty` for the size of the terminal, and fall back to `$COLUMNS`, which
+/// shells usually do not export.
+fn terminal_width() -> Option<usize> {
+    stty_columns().or_else(|| {
+        std::env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+    })
+}
+
+/// Get the number of columns of the terminal on stdout from `stty size`.
+fn stty_columns() -> Option<usize> {
+    // stty reads the size of the terminal on its stdin, so give it our stdout.
+    let stdout = std::io::stdout().as_fd().try_clone_to_owned().ok()?;
+    let output = std::process::Command::new("stty")
+        .arg("size")
+        .stdin(stdout)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let size = String::from_utf8(output.stdout).ok()?;
+    let (_, columns) = size.trim().split_once(' ')?;
+    columns.parse().ok()
+}
+
 #[derive(Debug, Args)]
 /// Options for package graphs.
 pub struct GraphOptions {
     /// Ignore optional dependencies.
     #[clap(long)]
     pub ignore_optdepends: bool,
-    /// Show less information.
-    #[clap(short = 'q', long = "quiet")]
-    pub quiet: bool,
+    /// Only follow optional dependencies of these packages.
+    ///
+    /// Ignore optional dependencies of all other packages.  May be given
+    /// multiple times.
+    #[clap(long, value_name = "PKG", conflicts_with = "ignore_optdepends")]
+    pub follow_optdepends_from: Vec<String>,
+    /// Only consider these packages and their dependencies.
+    ///
+    /// Restrict the graph to the given root packages and all packages they
+    /// transitively depend on.
+    #[clap(long, value_name = "PKG,...", value_delimiter = ',')]
+    pub roots: Vec<String>,
+    /// Read further root packages from a file.
+    ///
+    /// The file lists one package per line; blank lines and everything after
+    /// `#` are ignored.  Add these packages to --roots.
+    #[clap(long, value_name = "PATH")]
+    pub include_file: Option<PathBuf>,
+    /// Never list these packages.
+    ///
+    /// Omit these packages from lists, trees and dot graphs, but still follow
+    /// their dependencies.
+    #[clap(long, value_name = "PKG,...", value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    /// Read further packages to exclude from a file.
+    ///
+    /// The file lists one package per line; blank lines and everything after
+    /// `#` are ignored.  Add these packages to --exclude.
+    #[clap(long, value_name = "PATH")]
+    pub exclude_file: Option<PathBuf>,
+    /// Only list packages changed by the last pacman transaction.
+    ///
+    /// Read the pacman log to find all packages which the last transaction
+    /// installed, upgraded, downgraded or reinstalled.
+    #[clap(long, conflicts_with = "dot")]
+    pub since_upgrade: bool,
+    /// Only list foreign packages, which are not in any sync database.
+    ///
+    /// Foreign packages are typically installed from the AUR, like
+    /// `pacman -Qm` lists them.
+    #[clap(long, conflicts_with = "dot")]
+    pub only_foreign: bool,
+    /// Only list native packages, which are in a sync database.
+    #[clap(long, conflicts_with_all = ["dot", "only_foreign"])]
+    pub only_native: bool,
+    /// Only list packages with a newer version in a sync database.
+    ///
+    /// Like `pacman -Qu`, compare the installed version of each package with
+    /// the version in the first sync database which contains the package.
+    #[clap(long, conflicts_with_all = ["dot", "only_foreign"])]
+    pub newer_version_available: bool,
+    /// Only list packages with this install reason.
+    #[clap(long, value_enum, conflicts_with = "dot")]
+    pub reason: Option<Reason>,
+    #[clap(flatten)]
+    pub display_options: DisplayOptions,
     /// Render the graph as dot.
-    #[clap(long)]
+    #[clap(long, conflicts_with = "format")]
     pub dot: bool,
+    /// Add a `repo-<name>` class for the package repository to dot nodes.
+    ///
+    /// Foreign packages which are not in any sync database get the class
+    /// `repo-foreign`.
+    #[clap(long, requires = "dot")]
+    pub repo_classes: bool,
+    /// Link dot nodes to the Arch Linux package page of each package.
+    ///
+    /// Only packages in official Arch Linux repositories get a link.
+    #[clap(long, requires = "dot")]
+    pub link: bool,
+    /// Render a strict dot graph, which merges duplicate edges.
+    #[clap(long, requires = "dot")]
+    pub strict: bool,
+    /// Draw explicitly installed packages as boxes, and dependencies as ellipses.
+    #[clap(long, requires = "dot")]
+    pub shape_by_reason: bool,
+    /// How to draw package nodes.
+    ///
+    /// `record` draws each package as a table of its name, version, and
+    /// installed size, which some tools handle better than HTML-like labels.
+    #[clap(long, value_enum, requires = "dot", conflicts_with = "shape_by_reason")]
+    pub node_style: Option<NodeStyle>,
+    /// Weight edges of the given kind higher in the dot layout.
+    ///
+    /// Graphviz keeps edges with a higher weight shorter and straighter, so
+    /// `required` makes required dependencies dominate the layout.
+    #[clap(long, value_enum, requires = "dot")]
+    pub weight_by: Option<WeightBy>,
+    /// The style of edges for required dependencies.
+    #[clap(long, value_enum, value_name = "STYLE", default_value_t = EdgeStyle::Solid, requires = "dot")]
+    pub required_edges_style: EdgeStyle,
+    /// The style of edges for optional dependencies.
+    #[clap(long, value_enum, value_name = "STYLE", default_value_t = EdgeStyle::Dashed, requires = "dot")]
+    pub optional_edges_style: EdgeStyle,
+    /// Only render the transitive reduction of the graph.
+    ///
+    /// Omit every edge between two packages if the second package is also
+    /// reachable from the first one via other packages.  Retain all edges
+    /// within dependency cycles.
+    #[clap(long, requires = "dot")]
+    pub transitive_reduction: bool,
+    /// Collapse every dependency cycle into a single node.
+    ///
+    /// Draw all packages which transitively depend on each other as one node
+    /// labelled with all these packages, to get an acyclic graph which is
+    /// easier to read and lay out.
+    #[clap(
+        long,
+        requires = "dot",
+        conflicts_with_all = [
+            "transitive_reduction",
+            "virtual_nodes",
+            "collapse_repo",
+            "include_replaces_in_graph",
+            "show_degree",
+            "link",
+            "repo_classes",
+            "shape_by_reason",
+            "merge_optional_required",
+        ]
+    )]
+    pub condensation: bool,
+    /// Add nodes for provided names which packages depend on.
+    ///
+    /// Draw dependencies on a name which another package provides, e.g. a
+    /// virtual package like `cron`, as edges to a node for the name, and
+    /// connect that node to the providing package with a dotted edge.
+    #[clap(long, requires = "dot")]
+    pub virtual_nodes: bool,
+    /// Show the number of direct dependencies of each package.
+    #[clap(long, requires = "dot")]
+    pub show_degree: bool,
+    /// Whether to show versions in dot node labels.
+    ///
+    /// Defaults to showing versions unless --quiet.
+    #[clap(long, value_name = "BOOL", requires = "dot", action = ArgAction::Set)]
+    pub dot_versions: Option<bool>,
+    /// Color edges of dependencies which are both required and optional.
+    ///
+    /// Draw a single orange edge if a package both requires and optionally
+    /// requires another package, instead of only the required edge.
+    #[clap(long, requires = "dot")]
+    pub merge_optional_required: bool,
+    /// Add edges to packages which packages replace.
+    ///
+    /// Draw an orange edge from every package to each package it replaces,
+    /// and a dashed node for replaced packages which are no longer installed.
+    #[clap(long, requires = "dot")]
+    pub include_replaces_in_graph: bool,
+    /// The background color of the graph.
+    ///
+    /// Accept a Graphviz color name, e.g. `white`, or an RGB(A) hex color, e.g.
+    /// `#1e1e2e`.
+    #[clap(long, value_name = "COLOR", value_parser = parse_color, requires = "dot")]
+    pub bg_color: Option<String>,
+    /// The font for all text in the graph.
+    ///
+    /// Defaults to sans-serif for package names.
+    #[clap(long, value_name = "NAME", value_parser = parse_font, requires = "dot")]
+    pub font: Option<String>,
+    /// Collapse all packages of a repository into a single node.
+    ///
+    /// Draw one node for all packages from the repository, e.g. `core`, or
+    /// `foreign` for packages not in any repository, and connect it to all
+    /// packages which depend on or are dependencies of any of its packages.
+    #[clap(long, value_name = "NAME", requires = "dot")]
+    pub collapse_repo: Option<String>,
+    /// Write the dot graph to this file instead of stdout.
+    ///
+    /// Write dot to `.dot` and `.gv` files, and render `.svg`, `.png` and
+    /// `.pdf` files with Graphviz, which must be installed.
+    #[clap(long, value_name = "PATH", requires = "dot")]
+    pub output_graph: Option<PathBuf>,
+    /// Report progress on stderr while printing large dot graphs.
+    ///
+    /// Only report progress if stderr is a TTY.
+    #[clap(long, requires = "dot")]
+    pub progress: bool,
+    /// Clear the screen and list packages again whenever packages change.
+    ///
+    /// Watch the local package database, and re-run the command after every
+    /// pacman transaction, e.g. to follow orphans during a cleanup.  Stop with
+    /// Ctrl-C.
+    #[clap(long, conflicts_with_all = ["dot", "format"])]
+    pub watch: bool,
+    /// Print this string before every package.
+    ///
+    /// Turn a list of packages into commands, e.g. `--quiet --prefix 'sudo
+    /// pacman -Rns '` prints a command to remove each package.
+    #[clap(long, value_name = "STR", conflicts_with_all = ["dot", "format"])]
+    pub prefix: Option<String>,
+}
+
+/// Parse a Graphviz color name or RGB(A) hex color.
+fn parse_color(color: &str) -> Result<String, String> {
+    let valid = match color.strip_prefix('#') {
+        Some(hex) => matches!(hex.len(), 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => !color.is_empty() && color.chars().all(|c| c.is_ascii_alphanumeric()),
+    };
+    if valid {
+        Ok(color.to_string())
+    } else {
+        Err(format!(
+            "Expected a color name or #RRGGBB or #RRGGBBAA, got {color}"
+        ))
+    }
+}
+
+/// Parse a soname like `libssl.so=3-64`.
+fn parse_soname(value: &str) -> Result<String, String> {
+    let name = value.split_once('=').map_or(value, |(name, _)| name);
+    if name
+        .strip_suffix(".so")
+        .is_some_and(|stem| !stem.is_empty())
+    {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "Expected a soname like libssl.so=3-64, got {value}"
+        ))
+    }
+}
+
+/// Parse a font name which is safe to use in HTML-like labels.
+fn parse_font(font: &str) -> Result<String, String> {
+    if font.is_empty() || font.contains(['"', '<', '>', '&']) {
+        Err(format!("Invalid font name: {font}"))
+    } else {
+        Ok(font.to_string())
+    }
 }
 
 impl GraphOptions {
-    pub fn oneline_style(&self) -> PrintOneLine {
-        if self.quiet {
-            PrintOneLine::NameOnly
+    /// Whether to ignore any optional dependencies.
+    pub fn filters_optdepends(&self) -> bool {
+        self.ignore_optdepends || !self.follow_optdepends_from.is_empty()
+    }
+
+    /// Whether `package` is excluded from the output.
+    pub fn excludes(&self, package: &Package) -> bool {
+        self.exclude.iter().any(|name| name == package.name())
+    }
+
+    /// Whether to follow a dependency `edge` of the `dependent` package.
+    pub fn follows_edge(&self, dependent: PackageNode<'_>, edge: DependencyEdge) -> bool {
+        match edge {
+            DependencyEdge::Required => true,
+            DependencyEdge::Optional if self.follow_optdepends_from.is_empty() => {
+                !self.ignore_optdepends
+            }
+            DependencyEdge::Optional => self
+                .follow_optdepends_from
+                .iter()
+                .any(|name| name == dependent.name()),
+        }
+    }
+
+    pub fn repositories<'a>(&self, alpm: &'a Alpm) -> Repositories<'a> {
+        self.display_options.repositories(alpm)
+    }
+
+    pub fn oneline_style<'a>(&'a self, repositories: &'a Repositories<'a>) -> OneLineStyle<'a> {
+        OneLineStyle {
+            prefix: self.prefix.as_deref(),
+            ..self.display_options.oneline_style(repositories)
+        }
+    }
+
+    pub fn foreign(&self) -> Option<bool> {
+        if self.only_foreign {
+            Some(true)
+        } else if self.only_native {
+            Some(false)
         } else {
-            PrintOneLine::WithVersion
+            None
+        }
+    }
+
+    pub fn list_style<'a>(&'a self, repositories: &'a Repositories<'a>) -> ListStyle<'a> {
+        let with_prefix = |style| OneLineStyle {
+            prefix: self.prefix.as_deref(),
+            ..style
+        };
+        match self.display_options.list_style(repositories) {
+            ListStyle::OneLine(style) => ListStyle::OneLine(with_prefix(style)),
+            ListStyle::Aligned { style, max_width } => ListStyle::Aligned {
+                style: with_prefix(style),
+                max_width,
+            },
+            style @ ListStyle::Tsv { .. } => style,
+        }
+    }
+
+    pub fn dot_style<'a>(&'a self, repositories: &'a Repositories<'a>) -> DotStyle<'a> {
+        DotStyle {
+            label: match self.dot_versions {
+                Some(true) => PrintOneLine::WithVersion,
+                Some(false) => PrintOneLine::NameOnly,
+                None => self.display_options.print_one_line(),
+            },
+            plain_labels: self.display_options.quiet > 1,
+            repositories,
+            repository_classes: self.repo_classes,
+            links: self.link,
+            strict: self.strict,
+            shape_by_reason: self.shape_by_reason,
+            record_nodes: self.node_style == Some(NodeStyle::Record),
+            weight_required: self.weight_by == Some(WeightBy::Required),
+            merge_optional_required: self.merge_optional_required,
+            required_edges_style: self.required_edges_style.dot_name(),
+            optional_edges_style: self.optional_edges_style.dot_name(),
+            transitive_reduction: self.transitive_reduction,
+            condensation: self.condensation,
+            virtual_nodes: self.virtual_nodes,
+            show_degree: self.show_degree,
+            replaces: self.include_replaces_in_graph,
+            collapse_repository: self.collapse_repo.as_deref(),
+            background: self.bg_color.as_deref(),
+            font: self.font.as_deref(),
+            progress: self.progress && std::io::stderr().is_terminal(),
         }
     }
 }
@@ -6,16 +6,24 @@
 
 //! Analyse dependencies of ALPM packages.
 
-use std::collections::{HashSet, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    hash::{Hash, RandomState},
+};
 
-use alpm::{Package, PackageReason};
-use petgraph::visit::{
-    Bfs, GraphRef, IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount,
-    NodeFiltered, Reversed, VisitMap as _, Visitable, Walker,
+use alpm::{Db, Dep, Package, PackageReason};
+use petgraph::{
+    Direction,
+    algo::all_simple_paths,
+    visit::{
+        Bfs, Data, EdgeFiltered, EdgeRef, GraphRef, IntoEdges, IntoNeighbors,
+        IntoNeighborsDirected, IntoNodeIdentifiers, NodeCount, NodeFiltered, NodeIndexable,
+        Reversed, VisitMap as _, Visitable, Walker,
+    },
 };
 use tracing::{debug, debug_span};
 
-use crate::graph::PackageNode;
+use crate::graph::{DependencyEdge, PackageNode};
 
 /// Get the subgraph of all orphans in a dependency graph.
 ///
@@ -64,14 +72,233 @@ where
     NodeFiltered::from_fn(graph, move |node| !marked_pkgs.contains(&node))
 }
 
-/// Determine why a package was installed.
+/// Find all orphans in a database without building a dependency graph.
+///
+/// Like [`orphans`], but mark packages by following dependencies of explicitly
+/// installed packages directly in `db`, resolving each dependency to all
+/// installed packages which satisfy it, like the edges of the dependency
+/// graph.  This avoids materializing the whole dependency graph, at the
+/// expense of returning a plain list of packages.
+///
+/// Follow optional dependencies unless `ignore_optdepends` is set.
+///
+/// Return all orphans, in database order.
+#[must_use]
+pub fn orphans_in_db(db: &Db, ignore_optdepends: bool) -> Vec<&Package> {
+    let pkgs = db.pkgs().iter().collect::<Vec<_>>();
+    // Index packages by their names and provided names, to quickly find all
+    // candidates which may satisfy a dependency.
+    let mut candidates = HashMap::<&str, Vec<&Package>>::with_capacity(pkgs.len());
+    for pkg in &pkgs {
+        candidates.entry(pkg.name()).or_default().push(pkg);
+        for provide in pkg.provides() {
+            candidates.entry(provide.name()).or_default().push(pkg);
+        }
+    }
+    let satisfiers = |pkg: &Package| {
+        let _guard = debug_span!("mark", package = pkg.name()).entered();
+        let optdepends = pkg.optdepends();
+        let optdepends = (!ignore_optdepends).then_some(optdepends.iter());
+        pkg.depends()
+            .iter()
+            .chain(optdepends.into_iter().flatten())
+            .flat_map(|dep| {
+                candidates
+                    .get(dep.name())
+                    .into_iter()
+                    .flatten()
+                    .filter(move |candidate| {
+                        alpm_utils::depends::satisfies(
+                            dep,
+                            candidate.name(),
+                            candidate.version(),
+                            candidate.provides().iter(),
+                        )
+                    })
+                    .map(|candidate| PackageNode::new(candidate))
+            })
+            .collect::<Vec<_>>()
+    };
+    unreachable_from(
+        &pkgs
+            .iter()
+            .map(|pkg| PackageNode::new(pkg))
+            .collect::<Vec<_>>(),
+        |node| node.reason() == PackageReason::Explicit,
+        |node| satisfiers(node.package()),
+    )
+    .into_iter()
+    .map(PackageNode::package)
+    .collect()
+}
+
+/// Find all nodes which are not reachable from any root.
+///
+/// Start at all `nodes` for which `is_root` returns true, and follow
+/// `dependencies` of every node.  Return all other `nodes`, in order.
+fn unreachable_from<N, I>(
+    nodes: &[N],
+    is_root: impl Fn(N) -> bool,
+    dependencies: impl Fn(N) -> I,
+) -> Vec<N>
+where
+    N: Copy + Eq + Hash,
+    I: IntoIterator<Item = N>,
+{
+    let mut marked = HashSet::with_capacity(nodes.len());
+    let mut stack = nodes
+        .iter()
+        .copied()
+        .filter(|node| is_root(*node))
+        .collect::<Vec<_>>();
+    marked.extend(stack.iter().copied());
+    while let Some(node) = stack.pop() {
+        for dependency in dependencies(node) {
+            if marked.insert(dependency) {
+                stack.push(dependency);
+            }
+        }
+    }
+    nodes
+        .iter()
+        .copied()
+        .filter(|node| !marked.contains(node))
+        .collect()
+}
+
+/// Get the subgraph of all packages which are only optionally required.
+///
+/// Return a graph which contains all packages which are reachable from
+/// explicitly installed packages, but only through optional dependencies, i.e.
+/// packages which are orphans if optional dependencies are ignored, but not
+/// otherwise.
+pub fn optional_only<'a, G>(graph: G) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
+where
+    G: GraphRef
+        + NodeCount
+        + Visitable<NodeId = PackageNode<'a>>
+        + IntoEdges
+        + IntoNodeIdentifiers
+        + Data<EdgeWeight = DependencyEdge>,
+{
+    let required_graph = EdgeFiltered::from_fn(graph, |edge: G::EdgeRef| {
+        *edge.weight() == DependencyEdge::Required
+    });
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let required_orphans = orphans(&required_graph)
+        .node_identifiers()
+        .collect::<HashSet<_>>();
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let all_orphans = orphans(graph).node_identifiers().collect::<HashSet<_>>();
+    NodeFiltered::from_fn(graph, move |node| {
+        required_orphans.contains(&node) && !all_orphans.contains(&node)
+    })
+}
+
+/// Collect all packages reachable from explicitly installed packages.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn reachable_from_explicit<'a, G>(graph: G) -> HashSet<PackageNode<'a>>
+where
+    G: Visitable<NodeId = PackageNode<'a>> + IntoNeighbors + IntoNodeIdentifiers,
+{
+    let mut bfs = Bfs {
+        discovered: graph.visit_map(),
+        stack: VecDeque::new(),
+    };
+    for node in graph.node_identifiers() {
+        if node.reason() == PackageReason::Explicit && bfs.discovered.visit(node) {
+            bfs.stack.push_back(node);
+        }
+    }
+    bfs.iter(graph).collect()
+}
+
+/// Find packages which become orphans if a package is removed.
+///
+/// Return all packages in `graph` which are reachable from explicitly
+/// installed packages, but only through `package`, i.e. all packages which
+/// become orphans if `package` is removed.  Sort packages by name.
+pub fn removal_orphans<'a, G>(graph: G, package: &'a Package) -> Vec<PackageNode<'a>>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors + IntoNodeIdentifiers,
+{
+    let removed = PackageNode::new(package);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let remaining = reachable_from_explicit(&NodeFiltered::from_fn(graph, |node| node != removed));
+    let mut orphans = reachable_from_explicit(graph)
+        .into_iter()
+        .filter(|node| *node != removed && !remaining.contains(node))
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+    orphans
+}
+
+/// Find explicitly installed packages which other packages require anyway.
+///
+/// Return all explicitly installed packages in `graph` which are reachable from
+/// other explicitly installed packages, i.e. which would not become orphans if
+/// they were installed as dependencies.  Sort packages by name.
+pub fn redundant_explicit<'a, G>(graph: G) -> Vec<PackageNode<'a>>
+where
+    G: Visitable<NodeId = PackageNode<'a>>
+        + IntoNeighbors
+        + IntoNeighborsDirected
+        + IntoNodeIdentifiers,
+{
+    let explicit_pkgs = graph
+        .node_identifiers()
+        .filter(|node| node.reason() == PackageReason::Explicit)
+        .collect::<Vec<_>>();
+    let mut redundant = explicit_pkgs
+        .iter()
+        .copied()
+        .filter(|package| {
+            // Skip the traversal for packages which nothing depends on.
+            if graph
+                .neighbors_directed(*package, Direction::Incoming)
+                .next()
+                .is_none()
+            {
+                return false;
+            }
+            let mut bfs = Bfs {
+                discovered: graph.visit_map(),
+                stack: VecDeque::with_capacity(explicit_pkgs.len()),
+            };
+            for node in explicit_pkgs.iter().filter(|node| *node != package) {
+                bfs.discovered.visit(*node);
+                bfs.stack.push_back(*node);
+            }
+            bfs.iter(graph).any(|node| node == *package)
+        })
+        .collect::<Vec<_>>();
+    redundant.sort_by_key(|node| node.package().name());
+    redundant
+}
+
+/// Determine why packages were installed.
+///
+/// Return a graph which contains all `packages` and all packages which
+/// transitively depend on any of `packages`.
 ///
 /// # Panics
 ///
 /// If `graph` has negative edge weights, this function panics.
 pub fn dependents<'a, G>(
     graph: G,
-    package: &'a Package,
+    packages: &[&'a Package],
 ) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
 where
     G: GraphRef
@@ -82,7 +309,18 @@ where
         + IntoNodeIdentifiers,
 {
     let reversed = Reversed(graph);
-    let bfs = Bfs::new(&reversed, PackageNode::new(package));
+    // Start a BFS from all packages at once, to traverse shared dependents
+    // only once.
+    let mut bfs = Bfs {
+        discovered: reversed.visit_map(),
+        stack: VecDeque::with_capacity(packages.len()),
+    };
+    for package in packages {
+        let node = PackageNode::new(package);
+        if bfs.discovered.visit(node) {
+            bfs.stack.push_back(node);
+        }
+    }
     #[allow(
         clippy::mutable_key_type,
         reason = "We do not mutate the package pointer while traversing the graph"
@@ -90,3 +328,578 @@ where
     let reachable_nodes = bfs.iter(&reversed).collect::<HashSet<_>>();
     NodeFiltered::from_fn(graph, move |node| reachable_nodes.contains(&node))
 }
+
+/// Count the dependency paths from every node in `graph` to any of `targets`.
+///
+/// Count paths over the condensation of `graph`, i.e. treat every dependency
+/// cycle as a single node, so that the counts are finite and do not depend on
+/// traversal order.  Count every edge leaving a cycle as a separate path.  A
+/// cycle which contains a target has exactly one path.
+fn count_paths<N, G>(graph: G, targets: &HashSet<N>) -> HashMap<N, usize>
+where
+    N: Copy + Eq + Hash,
+    G: IntoNeighbors + IntoNodeIdentifiers<NodeId = N> + NodeIndexable,
+{
+    let mut counts = HashMap::new();
+    // tarjan_scc returns components in reverse topological order, so we
+    // always know the counts of all dependencies of a component.
+    for component in petgraph::algo::tarjan_scc(graph) {
+        let count = if component.iter().any(|node| targets.contains(node)) {
+            1
+        } else {
+            component
+                .iter()
+                .flat_map(|node| graph.neighbors(*node))
+                .filter(|dependency| !component.contains(dependency))
+                .filter_map(|dependency| counts.get(&dependency).copied())
+                .fold(0, usize::saturating_add)
+        };
+        counts.extend(component.iter().map(|node| (*node, count)));
+    }
+    counts
+}
+
+/// Count the dependency paths from dependents to packages.
+///
+/// Return every package in `graph` which transitively depends on any of
+/// `packages`, with the number of distinct dependency paths from the package
+/// to any of `packages`.  Treat every dependency cycle as a single package, to
+/// only count finite paths.  Sort packages by name.
+pub fn dependent_path_counts<'a, G>(
+    graph: G,
+    packages: &[&'a Package],
+) -> Vec<(PackageNode<'a>, usize)>
+where
+    G: IntoNeighbors + IntoNodeIdentifiers<NodeId = PackageNode<'a>> + NodeIndexable,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let targets = packages
+        .iter()
+        .map(|package| PackageNode::new(package))
+        .collect::<HashSet<_>>();
+    let mut path_counts = count_paths(graph, &targets)
+        .into_iter()
+        .filter(|(node, count)| !targets.contains(node) && 0 < *count)
+        .collect::<Vec<_>>();
+    path_counts.sort_by_key(|(node, _)| node.package().name());
+    path_counts
+}
+
+/// Get the subgraph of all dependencies of packages.
+///
+/// Return a graph which contains all `packages` and all packages which any of
+/// `packages` transitively depends on.
+pub fn dependencies<'a, G>(
+    graph: G,
+    packages: &[&'a Package],
+) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
+where
+    G: GraphRef + Visitable<NodeId = PackageNode<'a>> + IntoNeighbors,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let reachable_nodes = reachable(
+        graph,
+        packages.iter().map(|package| PackageNode::new(package)),
+    );
+    NodeFiltered::from_fn(graph, move |node| reachable_nodes.contains(&node))
+}
+
+/// Get all nodes in `graph` which are reachable from any of `roots`.
+///
+/// Return `roots` and all nodes which any of `roots` transitively points to.
+fn reachable<N, G>(graph: G, roots: impl IntoIterator<Item = N>) -> HashSet<N>
+where
+    N: Copy + Eq + Hash,
+    G: GraphRef + Visitable<NodeId = N> + IntoNeighbors,
+{
+    let mut bfs = Bfs {
+        discovered: graph.visit_map(),
+        stack: VecDeque::new(),
+    };
+    for node in roots {
+        if bfs.discovered.visit(node) {
+            bfs.stack.push_back(node);
+        }
+    }
+    bfs.iter(graph).collect()
+}
+
+/// Get the subgraph of direct dependents of packages.
+///
+/// Unlike [`dependents`] this does not follow dependencies transitively, but
+/// only includes packages with an edge directly into any of `packages`.
+///
+/// Return a graph which contains `packages` and all their direct dependents.
+pub fn direct_dependents<'a, G>(
+    graph: G,
+    packages: &[&'a Package],
+) -> NodeFiltered<G, impl Fn(PackageNode<'a>) -> bool>
+where
+    G: GraphRef + IntoNeighborsDirected<NodeId = PackageNode<'a>>,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let direct_nodes = packages
+        .iter()
+        .map(|package| PackageNode::new(package))
+        .flat_map(|node| {
+            graph
+                .neighbors_directed(node, Direction::Incoming)
+                .chain(std::iter::once(node))
+        })
+        .collect::<HashSet<_>>();
+    NodeFiltered::from_fn(graph, move |node| direct_nodes.contains(&node))
+}
+
+/// Find packages which depend on a name provided by a package.
+///
+/// Return all packages in `db` which depend on a name that `package`
+/// provides, and which no other package in `db` satisfies, i.e. which would
+/// break if `package` were removed even though they do not depend on the real
+/// name of `package`.  Each package is returned together with the dependency
+/// that `package` satisfies.
+#[must_use]
+pub fn provided_dependents<'a>(db: &'a Db, package: &'a Package) -> Vec<(&'a Package, &'a Dep)> {
+    let provides = package.provides();
+    let has_other_satisfier = |dep: &Dep| {
+        db.pkgs().iter().any(|pkg| {
+            !std::ptr::eq(pkg, package)
+                && alpm_utils::depends::satisfies(
+                    dep,
+                    pkg.name(),
+                    pkg.version(),
+                    pkg.provides().iter(),
+                )
+        })
+    };
+    db.pkgs()
+        .iter()
+        .filter(|pkg| !std::ptr::eq(*pkg, package))
+        .flat_map(|pkg| {
+            pkg.depends()
+                .iter()
+                .filter(|dep| {
+                    dep.name() != package.name()
+                        && provides
+                            .iter()
+                            .any(|provide| alpm_utils::depends::satisfies_provide(*dep, provide))
+                        && !has_other_satisfier(dep)
+                })
+                .map(move |dep| (pkg, dep))
+        })
+        .collect()
+}
+
+/// Find providers and dependents of a virtual name.
+///
+/// Return all packages in `db` which provide `name`, and all packages in `db`
+/// which require or optionally require `name`, each with the kind of the
+/// dependency.  Ignore the versions of provides and dependencies.
+#[must_use]
+pub fn virtual_dependency<'a>(
+    db: &'a Db,
+    name: &str,
+) -> (Vec<&'a Package>, Vec<(&'a Package, DependencyEdge)>) {
+    let pkgs = db.pkgs();
+    let providers = pkgs
+        .iter()
+        .filter(|pkg| pkg.provides().iter().any(|provide| provide.name() == name))
+        .collect();
+    let dependents = pkgs
+        .iter()
+        .filter_map(|pkg| {
+            if pkg.depends().iter().any(|dep| dep.name() == name) {
+                Some((pkg, DependencyEdge::Required))
+            } else if pkg.optdepends().iter().any(|dep| dep.name() == name) {
+                Some((pkg, DependencyEdge::Optional))
+            } else {
+                None
+            }
+        })
+        .collect();
+    (providers, dependents)
+}
+
+/// Find packages in sync databases which depend on a package.
+///
+/// Return all packages in `syncdbs` which are not installed in `localdb`, but
+/// directly require or optionally require `package`, or a name it provides,
+/// i.e. which would depend on `package` if installed.  Return each package
+/// with its database and the kind of the dependency.  If several databases
+/// contain a package of the same name, only return the first.
+#[must_use]
+pub fn sync_dependents<'a>(
+    localdb: &Db,
+    syncdbs: &[&'a Db],
+    package: &Package,
+) -> Vec<(&'a Db, &'a Package, DependencyEdge)> {
+    let satisfied_by_package = |dep: &Dep| {
+        alpm_utils::depends::satisfies(
+            dep,
+            package.name(),
+            package.version(),
+            package.provides().iter(),
+        )
+    };
+    let mut seen = HashSet::new();
+    let mut dependents = Vec::new();
+    for db in syncdbs {
+        for pkg in db.pkgs() {
+            if localdb.pkg(pkg.name()).is_ok() || !seen.insert(pkg.name()) {
+                continue;
+            }
+            if pkg.depends().iter().any(satisfied_by_package) {
+                dependents.push((*db, pkg, DependencyEdge::Required));
+            } else if pkg.optdepends().iter().any(satisfied_by_package) {
+                dependents.push((*db, pkg, DependencyEdge::Optional));
+            }
+        }
+    }
+    dependents
+}
+
+/// Get the optional dependencies of a package.
+///
+/// Return all optional dependencies of `package`, each with the first package
+/// in `db` which satisfies it, or `None` if no package in `db` satisfies it.
+#[must_use]
+pub fn optional_dependencies<'a>(
+    db: &'a Db,
+    package: &'a Package,
+) -> Vec<(&'a Dep, Option<&'a Package>)> {
+    package
+        .optdepends()
+        .iter()
+        .map(|dep| (dep, db.pkgs().find_satisfier(dep.to_string())))
+        .collect()
+}
+
+/// Find dependencies which no package satisfies.
+///
+/// Return every package in `db` together with each of its dependencies which
+/// no package in `db` satisfies, either because no package of the name is
+/// installed, or because the installed package has the wrong version.
+#[must_use]
+pub fn unsatisfied_dependencies(db: &Db) -> Vec<(&Package, &Dep)> {
+    let pkgs = db.pkgs();
+    pkgs.iter()
+        .flat_map(|pkg| pkg.depends().iter().map(move |dep| (pkg, dep)))
+        .filter(|(_, dep)| pkgs.find_satisfier(dep.to_string()).is_none())
+        .collect()
+}
+
+/// Find dependencies of a package which are not installed.
+///
+/// Return all required dependencies of `package` which no package in `db`
+/// satisfies by name, i.e. which are neither installed nor provided by any
+/// installed package, regardless of their version.
+#[must_use]
+pub fn missing_dependencies<'a>(db: &'a Db, package: &'a Package) -> Vec<&'a Dep> {
+    let pkgs = db.pkgs();
+    package
+        .depends()
+        .iter()
+        .filter(|dep| pkgs.find_satisfier(dep.name()).is_none())
+        .collect()
+}
+
+/// Find dependency cycles.
+///
+/// Return all strongly connected components of `graph` with more than one
+/// package, i.e. all sets of packages which transitively depend on each other.
+/// Sort the packages of each cycle by name.
+pub fn cycles<'a, G>(graph: G) -> Vec<Vec<PackageNode<'a>>>
+where
+    G: IntoNeighbors + IntoNodeIdentifiers<NodeId = PackageNode<'a>> + NodeIndexable,
+{
+    let mut cycles = petgraph::algo::tarjan_scc(graph)
+        .into_iter()
+        .filter(|component| 1 < component.len())
+        .collect::<Vec<_>>();
+    for cycle in &mut cycles {
+        cycle.sort_by_key(|node| node.package().name());
+    }
+    cycles
+}
+
+/// Find the longest dependency chain.
+///
+/// Return the longest path of dependency edges in `graph`, starting at the
+/// package which depends on others most deeply.  Condense dependency cycles
+/// first, i.e. ignore edges between packages of the same cycle, so that the
+/// longest path is well-defined.  Among equally long chains prefer packages
+/// with smaller names.
+pub fn longest_chain<'a, G>(graph: G) -> Vec<PackageNode<'a>>
+where
+    G: IntoNeighbors + IntoNodeIdentifiers<NodeId = PackageNode<'a>> + NodeIndexable,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut chains: HashMap<PackageNode<'a>, (usize, Option<PackageNode<'a>>)> = HashMap::new();
+    // tarjan_scc returns components in reverse topological order, so we
+    // always know the chains of all dependencies of a component.
+    for component in petgraph::algo::tarjan_scc(graph) {
+        for &node in &component {
+            let next = graph
+                .neighbors(node)
+                .filter(|neighbor| !component.contains(neighbor))
+                .filter_map(|neighbor| chains.get(&neighbor).map(|(len, _)| (*len, neighbor)))
+                .max_by_key(|(len, neighbor)| (*len, std::cmp::Reverse(neighbor.package().name())));
+            let chain = next.map_or((1, None), |(len, neighbor)| (len + 1, Some(neighbor)));
+            chains.insert(node, chain);
+        }
+    }
+    let mut node = chains
+        .iter()
+        .max_by_key(|(node, (len, _))| (*len, std::cmp::Reverse(node.package().name())))
+        .map(|(node, _)| *node);
+    let mut chain = Vec::new();
+    while let Some(current) = node {
+        chain.push(current);
+        node = chains.get(&current).and_then(|(_, next)| *next);
+    }
+    chain
+}
+
+/// Get the distance of all dependencies of a package.
+///
+/// Return `package` and all packages which `package` transitively depends on
+/// in `graph`, each with the minimum number of dependency edges from
+/// `package`.  Sort packages by distance first, and by name second.
+pub fn dependency_distances<'a, G>(graph: G, package: &'a Package) -> Vec<(PackageNode<'a>, usize)>
+where
+    G: IntoNeighbors<NodeId = PackageNode<'a>>,
+{
+    let root = PackageNode::new(package);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut visited = HashSet::from([root]);
+    let mut distances = vec![(root, 0)];
+    let mut queue = VecDeque::from([(root, 0)]);
+    while let Some((node, distance)) = queue.pop_front() {
+        for neighbor in graph.neighbors(node) {
+            if visited.insert(neighbor) {
+                distances.push((neighbor, distance + 1));
+                queue.push_back((neighbor, distance + 1));
+            }
+        }
+    }
+    distances.sort_by_key(|(node, distance)| (*distance, node.package().name()));
+    distances
+}
+
+/// Get all dependency paths from one package to another.
+///
+/// Return every simple path of dependency edges in `graph` from `from` to
+/// `to`, each including `from` and `to`, but at most `max_paths` paths.
+pub fn dependency_paths<'a, G>(
+    graph: G,
+    from: &'a Package,
+    to: &'a Package,
+    max_paths: usize,
+) -> Vec<Vec<PackageNode<'a>>>
+where
+    G: NodeCount + IntoNeighborsDirected<NodeId = PackageNode<'a>>,
+{
+    all_simple_paths::<Vec<_>, _, RandomState>(
+        graph,
+        PackageNode::new(from),
+        PackageNode::new(to),
+        0,
+        None,
+    )
+    .take(max_paths)
+    .collect()
+}
+
+/// Get the distance of all dependents of packages.
+///
+/// Return all `packages` and all packages which transitively depend on any of
+/// `packages` in `graph`, each with the minimum number of dependency edges to
+/// the nearest of `packages`.  Sort packages by distance first, and by name
+/// second.
+pub fn dependent_distances<'a, G>(
+    graph: G,
+    packages: &[&'a Package],
+) -> Vec<(PackageNode<'a>, usize)>
+where
+    G: IntoNeighborsDirected<NodeId = PackageNode<'a>>,
+{
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut visited = packages
+        .iter()
+        .copied()
+        .map(PackageNode::new)
+        .collect::<HashSet<_>>();
+    let mut distances = visited.iter().map(|node| (*node, 0)).collect::<Vec<_>>();
+    let mut queue = distances.iter().copied().collect::<VecDeque<_>>();
+    while let Some((node, distance)) = queue.pop_front() {
+        for neighbor in graph.neighbors_directed(node, Direction::Incoming) {
+            if visited.insert(neighbor) {
+                distances.push((neighbor, distance + 1));
+                queue.push_back((neighbor, distance + 1));
+            }
+        }
+    }
+    distances.sort_by_key(|(node, distance)| (*distance, node.package().name()));
+    distances
+}
+
+/// Get the degree centrality of all packages.
+///
+/// Return every package in `graph` with its degree, i.e. the number of its
+/// dependencies and dependents.  Packages with a high degree hold the graph
+/// together.  Sort packages by degree in descending order first, and by name
+/// second.
+pub fn degree_centrality<'a, G>(graph: G) -> Vec<(PackageNode<'a>, usize)>
+where
+    G: IntoNeighborsDirected + IntoNodeIdentifiers<NodeId = PackageNode<'a>>,
+{
+    let mut degrees = graph
+        .node_identifiers()
+        .map(|node| {
+            let degree = graph.neighbors_directed(node, Direction::Outgoing).count()
+                + graph.neighbors_directed(node, Direction::Incoming).count();
+            (node, degree)
+        })
+        .collect::<Vec<_>>();
+    degrees.sort_by_key(|(node, degree)| (std::cmp::Reverse(*degree), node.package().name()));
+    degrees
+}
+
+/// Whether a package both requires and optionally requires another.
+///
+/// A dependency graph only has a required edge in this case, see
+/// [`crate::graph::build_graph_for_localdb`].
+#[must_use]
+pub fn is_required_and_optional(dependent: &Package, dependency: &Package) -> bool {
+    dependency
+        .required_by()
+        .iter()
+        .any(|name| name == dependent.name())
+        && dependency
+            .optional_for()
+            .iter()
+            .any(|name| name == dependent.name())
+}
+
+/// Find the provided name through which a package depends on another.
+///
+/// If `dependent` depends on `provider` through a name which `provider`
+/// provides, e.g. a virtual package like `cron`, return the dependency of
+/// `dependent` on that name.  Return `None` if `dependent` depends on
+/// `provider` by its real name, or not at all.  Look at optional or required
+/// dependencies of `dependent`, according to `kind`.
+#[must_use]
+pub fn provided_dependency<'a>(
+    dependent: &'a Package,
+    provider: &Package,
+    kind: DependencyEdge,
+) -> Option<&'a Dep> {
+    let dependencies = match kind {
+        DependencyEdge::Required => dependent.depends(),
+        DependencyEdge::Optional => dependent.optdepends(),
+    };
+    if dependencies.iter().any(|dep| dep.name() == provider.name()) {
+        return None;
+    }
+    let provided_names = provider.provides();
+    dependencies.into_iter().find(|dep| {
+        provided_names
+            .iter()
+            .any(|provide| alpm_utils::depends::satisfies_provide(*dep, provide))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use petgraph::prelude::DiGraphMap;
+
+    use petgraph::visit::EdgeFiltered;
+
+    use super::{count_paths, reachable, unreachable_from};
+    use crate::graph::DependencyEdge;
+
+    #[test]
+    fn count_paths_without_cycles() {
+        let graph = DiGraphMap::<u32, ()>::from_edges([(1, 2), (1, 3), (2, 4), (3, 4)]);
+        let counts = count_paths(&graph, &HashSet::from([4]));
+        assert_eq!(counts, HashMap::from([(1, 2), (2, 1), (3, 1), (4, 1)]));
+    }
+
+    #[test]
+    fn count_paths_with_cycle() {
+        let edges = [(1, 2), (2, 1), (2, 3), (1, 3), (4, 1), (4, 3)];
+        let expected = HashMap::from([(1, 2), (2, 2), (3, 1), (4, 3)]);
+        let graph = DiGraphMap::<u32, ()>::from_edges(edges);
+        assert_eq!(count_paths(&graph, &HashSet::from([3])), expected);
+        // The counts must not depend on the order of traversal.
+        let graph = DiGraphMap::<u32, ()>::from_edges(edges.into_iter().rev());
+        assert_eq!(count_paths(&graph, &HashSet::from([3])), expected);
+    }
+
+    #[test]
+    fn count_paths_with_target_in_cycle() {
+        let graph = DiGraphMap::<u32, ()>::from_edges([(1, 2), (2, 1), (3, 1)]);
+        let counts = count_paths(&graph, &HashSet::from([2]));
+        assert_eq!(counts, HashMap::from([(1, 1), (2, 1), (3, 1)]));
+    }
+
+    #[test]
+    fn reachable_along_required_edges() {
+        // 1 requires 2 and optionally requires 3, which requires 4.
+        let graph = DiGraphMap::<u32, DependencyEdge>::from_edges([
+            (1, 2, DependencyEdge::Required),
+            (1, 3, DependencyEdge::Optional),
+            (3, 4, DependencyEdge::Required),
+        ]);
+        assert_eq!(reachable(&graph, [1]), HashSet::from([1, 2, 3, 4]));
+        let required_graph =
+            EdgeFiltered::from_fn(&graph, |(_, _, edge)| *edge == DependencyEdge::Required);
+        assert_eq!(reachable(&required_graph, [1]), HashSet::from([1, 2]));
+    }
+
+    #[test]
+    fn unreachable_from_agrees_with_graph() {
+        // 1 is explicit and depends on a virtual name which both 2 and 3
+        // provide; 3 depends on 4.  5 is not required by anything.
+        let nodes = [1, 2, 3, 4, 5];
+        let graph = DiGraphMap::<u32, DependencyEdge>::from_edges([
+            (1, 2, DependencyEdge::Required),
+            (1, 3, DependencyEdge::Required),
+            (3, 4, DependencyEdge::Optional),
+        ]);
+        let satisfiers = |node| match node {
+            1 => vec![2, 3],
+            3 => vec![4],
+            _ => Vec::new(),
+        };
+        let marked = reachable(&graph, [1]);
+        let graph_orphans = nodes
+            .into_iter()
+            .filter(|node| !marked.contains(node))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unreachable_from(&nodes, |node| node == 1, satisfiers),
+            graph_orphans
+        );
+        assert_eq!(graph_orphans, vec![5]);
+    }
+}
@@ -6,10 +6,18 @@
 
 //! Graphs of ALPM packages.
 
-use std::{fmt::Display, hash::Hash, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    hash::Hash,
+    ops::Deref,
+};
 
 use alpm::{Db, Package, Pkg};
-use petgraph::prelude::DiGraphMap;
+use petgraph::{
+    prelude::DiGraphMap,
+    visit::{Data, EdgeRef, IntoEdgeReferences, IntoNodeIdentifiers},
+};
 use tracing::{debug, debug_span, warn};
 
 /// The weight of a dependency edge.
@@ -110,6 +118,8 @@ pub type AlpmDepGraphMap<'a> = DiGraphMap<PackageNode<'a>, DependencyEdge>;
 /// `required_by` and `optional_for` edges.  This only works for the local
 /// database, but guarantees to return resolvable dependencies, so the returned
 /// graph is complete.
+///
+/// The graph has no self-loops: Skip packages which depend on themselves.
 pub fn build_graph_for_localdb(db: &Db) -> AlpmDepGraphMap<'_> {
     let mut g = DiGraphMap::new();
     for package in db.pkgs() {
@@ -120,54 +130,240 @@ pub fn build_graph_for_localdb(db: &Db) -> AlpmDepGraphMap<'_> {
             package.name()
         );
         g.add_node(PackageNode(package));
-        for requiree in package.required_by() {
-            match db.pkg(requiree.as_str()) {
-                Ok(requiree) => {
-                    debug!(
-                        package = requiree.name(),
-                        "Adding required edge {} -> {}",
-                        requiree.name(),
-                        package.name()
-                    );
-                    g.add_edge(
-                        PackageNode(requiree),
-                        PackageNode(package),
-                        DependencyEdge::Required,
-                    );
-                }
+        let dependents = package
+            .required_by()
+            .into_iter()
+            .map(|name| (name, DependencyEdge::Required))
+            .chain(
+                package
+                    .optional_for()
+                    .into_iter()
+                    .map(|name| (name, DependencyEdge::Optional)),
+            );
+        for (dependent, edge) in dependents {
+            match db.pkg(dependent.as_str()) {
+                Ok(dependent) => add_dependency_edge(&mut g, dependent, package, edge),
                 Err(error) => {
                     warn!(
-                        package = &requiree,
-                        "Package {} is required by {requiree} which was not found in local database: {error}",
+                        package = &dependent,
+                        "Package {} is required by {dependent} which was not found in local database: {error}",
                         package.name(),
                     );
                 }
             }
         }
-        for opt_requiree in package.optional_for() {
-            match db.pkg(opt_requiree.as_str()) {
-                Ok(opt_requiree) => {
-                    debug!(
-                        package = opt_requiree.name(),
-                        "Adding optional edge {} -> {}",
-                        opt_requiree.name(),
-                        package.name()
-                    );
-                    g.add_edge(
-                        PackageNode(opt_requiree),
-                        PackageNode(package),
-                        DependencyEdge::Optional,
-                    );
-                }
-                Err(error) => {
-                    warn!(
-                        package = &opt_requiree,
-                        "Package {} is required by {opt_requiree} which was not found in local database: {error}",
-                        package.name(),
-                    );
-                }
-            }
+    }
+    g
+}
+
+/// Why an edge does not go into a dependency graph.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SkippedEdge {
+    /// The package depends on itself.
+    SelfLoop,
+    /// There is a required edge already, which takes precedence over an
+    /// optional one.
+    Superseded,
+}
+
+/// Whether to skip an edge of kind `edge` from `dependent` to `dependency`.
+///
+/// `existing` is the edge already in the graph between both packages, if any.
+/// Return why to skip the edge, or `None` to add it.
+fn skip_edge<T>(
+    dependent: &T,
+    dependency: &T,
+    existing: Option<DependencyEdge>,
+    edge: DependencyEdge,
+) -> Option<SkippedEdge> {
+    if std::ptr::eq(dependent, dependency) {
+        // A self-loop would make a package its own dependent, so skip it.
+        Some(SkippedEdge::SelfLoop)
+    } else if edge == DependencyEdge::Optional && existing == Some(DependencyEdge::Required) {
+        // The graph has at most one edge between two packages.
+        Some(SkippedEdge::Superseded)
+    } else {
+        None
+    }
+}
+
+/// Add an edge of kind `edge` from `dependent` to `dependency` to `g`.
+///
+/// Skip self-loops, and optional edges between packages which already have a
+/// required edge.
+fn add_dependency_edge<'a>(
+    g: &mut AlpmDepGraphMap<'a>,
+    dependent: &'a Package,
+    dependency: &'a Package,
+    edge: DependencyEdge,
+) {
+    let existing = g
+        .edge_weight(PackageNode(dependent), PackageNode(dependency))
+        .copied();
+    match skip_edge(dependent, dependency, existing, edge) {
+        Some(SkippedEdge::SelfLoop) => {
+            warn!(
+                package = dependency.name(),
+                "Package {} has a {edge} dependency on itself, ignoring",
+                dependency.name()
+            );
+        }
+        Some(SkippedEdge::Superseded) => {
+            debug!(
+                package = dependent.name(),
+                "Skipping {edge} edge {} -> {} in favour of required edge",
+                dependent.name(),
+                dependency.name()
+            );
         }
+        None => {
+            debug!(
+                package = dependent.name(),
+                "Adding {edge} edge {} -> {}",
+                dependent.name(),
+                dependency.name()
+            );
+            g.add_edge(PackageNode(dependent), PackageNode(dependency), edge);
+        }
+    }
+}
+
+/// Collect a graph into a new dependency graph.
+///
+/// Copy all nodes and edges of `graph` into a new dependency graph, e.g. to
+/// materialize a filtered view of another graph.
+pub fn collect_graph<'a, G>(graph: G) -> AlpmDepGraphMap<'a>
+where
+    G: Data<EdgeWeight = DependencyEdge>
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>
+        + IntoEdgeReferences,
+{
+    let mut g = DiGraphMap::new();
+    for node in graph.node_identifiers() {
+        g.add_node(node);
+    }
+    for edge in graph.edge_references() {
+        g.add_edge(edge.source(), edge.target(), *edge.weight());
     }
     g
 }
+
+/// Compute the transitive reduction of a dependency graph.
+///
+/// Remove every edge whose target is also reachable from its source via other
+/// packages.  To handle dependency cycles, reduce the condensation of
+/// `graph`, i.e. the graph of its strongly connected components: Retain all
+/// edges within a strongly connected component, and all edges between two
+/// components unless the second component is also reachable from the first
+/// via other components.
+#[must_use]
+pub fn transitive_reduction<'a>(graph: &AlpmDepGraphMap<'a>) -> AlpmDepGraphMap<'a> {
+    // tarjan_scc returns components in reverse topological order, so every
+    // component comes after all components it depends on.
+    let components = petgraph::algo::tarjan_scc(graph);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let component_of = components
+        .iter()
+        .enumerate()
+        .flat_map(|(index, component)| component.iter().map(move |node| (*node, index)))
+        .collect::<HashMap<_, _>>();
+    let mut successors = vec![HashSet::new(); components.len()];
+    for (from, to, _) in graph.all_edges() {
+        if let (Some(&from), Some(&to)) = (component_of.get(&from), component_of.get(&to))
+            && from != to
+            && let Some(successors) = successors.get_mut(from)
+        {
+            successors.insert(to);
+        }
+    }
+    let mut reachable = Vec::<HashSet<usize>>::with_capacity(components.len());
+    for component_successors in &successors {
+        let mut component_reachable = component_successors.clone();
+        for successor in component_successors {
+            if let Some(successor_reachable) = reachable.get(*successor) {
+                component_reachable.extend(successor_reachable);
+            }
+        }
+        reachable.push(component_reachable);
+    }
+    let is_redundant = |from: usize, to: usize| {
+        successors.get(from).is_some_and(|successors| {
+            successors.iter().any(|successor| {
+                *successor != to
+                    && reachable
+                        .get(*successor)
+                        .is_some_and(|reachable| reachable.contains(&to))
+            })
+        })
+    };
+
+    let mut reduced = DiGraphMap::new();
+    for node in graph.nodes() {
+        reduced.add_node(node);
+    }
+    for (from, to, edge) in graph.all_edges() {
+        let redundant = match (component_of.get(&from), component_of.get(&to)) {
+            (Some(&from), Some(&to)) => from != to && is_redundant(from, to),
+            _ => false,
+        };
+        if !redundant {
+            reduced.add_edge(from, to, *edge);
+        }
+    }
+    reduced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DependencyEdge, SkippedEdge, skip_edge};
+
+    #[test]
+    fn skip_edge_self_loop() {
+        let package = "foo";
+        for edge in [DependencyEdge::Required, DependencyEdge::Optional] {
+            assert_eq!(
+                skip_edge(&package, &package, None, edge),
+                Some(SkippedEdge::SelfLoop)
+            );
+        }
+    }
+
+    #[test]
+    fn skip_edge_distinct_packages() {
+        let (dependent, dependency) = ("foo", "bar");
+        assert_eq!(
+            skip_edge(&dependent, &dependency, None, DependencyEdge::Required),
+            None
+        );
+        assert_eq!(
+            skip_edge(&dependent, &dependency, None, DependencyEdge::Optional),
+            None
+        );
+    }
+
+    #[test]
+    fn skip_edge_optional_after_required() {
+        let (dependent, dependency) = ("foo", "bar");
+        assert_eq!(
+            skip_edge(
+                &dependent,
+                &dependency,
+                Some(DependencyEdge::Required),
+                DependencyEdge::Optional
+            ),
+            Some(SkippedEdge::Superseded)
+        );
+        assert_eq!(
+            skip_edge(
+                &dependent,
+                &dependency,
+                Some(DependencyEdge::Optional),
+                DependencyEdge::Required
+            ),
+            None
+        );
+    }
+}
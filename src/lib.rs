@@ -36,3 +36,4 @@
 pub mod alpm;
 pub mod dependencies;
 pub mod graph;
+pub mod log;
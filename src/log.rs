@@ -0,0 +1,91 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL-1.2 OR GPL-3.0
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Read the pacman log.
+
+use std::{collections::HashSet, io::BufRead};
+
+/// Get all packages changed by the last transaction in a pacman log.
+///
+/// Read a pacman log from `log`, and return the names of all packages which
+/// the last transaction installed, upgraded, downgraded or reinstalled.
+///
+/// # Errors
+///
+/// Return an error if reading from `log` fails.
+pub fn last_transaction_packages<R: BufRead>(log: R) -> std::io::Result<HashSet<String>> {
+    let mut packages = HashSet::new();
+    for line in log.split(b'\n') {
+        // The log may contain arbitrary output of install scriptlets, so don't
+        // insist on UTF-8.
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let Some((_, message)) = line.split_once("] [ALPM] ") else {
+            continue;
+        };
+        if message.trim_end() == "transaction started" {
+            packages.clear();
+        } else if let Some((action, details)) = message.split_once(' ')
+            && matches!(
+                action,
+                "installed" | "upgraded" | "downgraded" | "reinstalled"
+            )
+            && let Some((name, _)) = details.split_once(' ')
+        {
+            packages.insert(name.to_owned());
+        }
+    }
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::last_transaction_packages;
+
+    fn packages(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|name| (*name).to_owned()).collect()
+    }
+
+    #[test]
+    fn empty_log() {
+        assert_eq!(last_transaction_packages(&b""[..]).unwrap(), packages(&[]));
+    }
+
+    #[test]
+    fn single_transaction() {
+        let log = "\
+[2025-01-01T10:00:00+0100] [PACMAN] Running 'pacman -Syu'
+[2025-01-01T10:00:01+0100] [ALPM] transaction started
+[2025-01-01T10:00:02+0100] [ALPM] upgraded linux (6.12.7-1 -> 6.12.8-1)
+[2025-01-01T10:00:02+0100] [ALPM-SCRIPTLET] ==> Building image
+[2025-01-01T10:00:03+0100] [ALPM] installed foo (1.0-1)
+[2025-01-01T10:00:03+0100] [ALPM] removed bar (2.0-1)
+[2025-01-01T10:00:04+0100] [ALPM] transaction completed
+";
+        assert_eq!(
+            last_transaction_packages(log.as_bytes()).unwrap(),
+            packages(&["linux", "foo"])
+        );
+    }
+
+    #[test]
+    fn interrupted_transaction() {
+        let log = "\
+[2025-01-01T10:00:01+0100] [ALPM] transaction started
+[2025-01-01T10:00:02+0100] [ALPM] installed foo (1.0-1)
+[2025-01-01T10:00:03+0100] [ALPM] transaction completed
+[2025-01-02T10:00:01+0100] [ALPM] transaction started
+[2025-01-02T10:00:02+0100] [ALPM] downgraded bar (2.0-1 -> 1.0-1)
+[2025-01-02T10:00:02+0100] [ALPM] reinstalled baz (1.0-1)
+";
+        assert_eq!(
+            last_transaction_packages(log.as_bytes()).unwrap(),
+            packages(&["bar", "baz"])
+        );
+    }
+}
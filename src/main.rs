@@ -31,24 +31,58 @@
 )]
 #![forbid(unsafe_code)]
 
-use alpm::{Alpm, Package};
-use clap::Parser;
-use packit::graph::{DependencyEdge, PackageNode};
-use petgraph::visit::{
-    Data, EdgeFiltered, EdgeRef, GraphProp, GraphRef, IntoEdgeReferences, IntoNeighbors,
-    IntoNeighborsDirected, IntoNodeIdentifiers, IntoNodeReferences, NodeCount, NodeIndexable,
-    Visitable,
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque, hash_map::Entry},
+    ffi::OsStr,
+    fs::File,
+    io::{BufReader, Write},
+    path::Path,
+    process::Stdio,
+    sync::mpsc::{RecvTimeoutError, Sender},
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "interactive")]
+use std::io::IsTerminal;
+
+use alpm::{Alpm, Package, PackageReason, Pkg};
+use clap::{CommandFactory, FromArgMatches};
+use packit::{
+    alpm::Repositories,
+    graph::{AlpmDepGraphMap, DependencyEdge, PackageNode},
+};
+use petgraph::{
+    Direction,
+    visit::{
+        Data, EdgeFiltered, EdgeRef, GraphProp, GraphRef, IntoEdgeReferences, IntoEdgesDirected,
+        IntoNeighbors, IntoNeighborsDirected, IntoNodeIdentifiers, IntoNodeReferences, NodeCount,
+        NodeFiltered, NodeIndexable, Visitable,
+    },
+};
+use tracing::level_filters::LevelFilter;
+
 use crate::{
     args::CliArgs,
-    print::{print_package_graph, print_package_one_line},
+    print::{
+        ListStyle, OneLineStyle, TreeConnectors, TreeStyle, print_dependency, print_error,
+        print_group_header, print_group_sizes, print_group_sizes_tsv, print_install_history,
+        print_orphan_annotated, print_orphan_report, print_package_graph, print_package_one_line,
+        print_package_one_line_annotated, print_package_tree, print_packages,
+        print_packages_by_size, print_packages_in_order, print_packages_one_line,
+        print_provided_dependency_warning, print_virtual_dependency_graph, print_warning,
+    },
 };
 
 mod args;
 mod print;
 
-fn list_orphans<'a, G>(options: &args::Orphans, graph: G) -> std::io::Result<()>
+fn list_orphans<'a, G>(
+    options: &args::Orphans,
+    graph: G,
+    pkg_graph: &AlpmDepGraphMap<'a>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()>
 where
     G: GraphRef
         + GraphProp
@@ -57,50 +91,918 @@ where
         + NodeIndexable
         + Visitable<NodeId = PackageNode<'a>>
         + IntoNeighbors
+        + IntoNeighborsDirected
         + IntoNodeIdentifiers
         + IntoEdgeReferences
         + IntoNodeReferences,
 {
     let orphans = packit::dependencies::orphans(&graph);
 
-    let mut stdout = anstream::stdout().lock();
+    let mut stdout = print::stdout();
 
     if options.graph_options.dot {
-        print_package_graph(&mut stdout, graph, options.graph_options.oneline_style())
+        print_graph(&mut stdout, graph, &options.graph_options, repositories)
     } else {
-        let mut orphan_nodes = orphans
-            .node_identifiers()
-            .map(PackageNode::package)
-            .collect::<Vec<_>>();
-        // Sort alphabetically
-        orphan_nodes.sort_by_key(|pkg| pkg.name());
+        print_orphan_list(
+            &mut stdout,
+            options,
+            &orphans,
+            pkg_graph,
+            repositories,
+            filter,
+        )
+    }
+}
 
-        for pkg in orphan_nodes {
-            print_package_one_line(&mut stdout, pkg, options.graph_options.oneline_style())?;
+/// Print `graph` as dot to `write`, or to the path given in `options`.
+fn print_graph<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    options: &args::GraphOptions,
+    repositories: &Repositories<'_>,
+) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>
+        + IntoNodeReferences
+        + NodeIndexable,
+{
+    let style = options.dot_style(repositories);
+    let graph = NodeFiltered::from_fn(graph, |node: PackageNode<'a>| {
+        !options.excludes(node.package())
+    });
+    let graph = &graph;
+    match &options.output_graph {
+        None => print_package_graph(write, graph, &style),
+        Some(path) => {
+            let mut dot = Vec::new();
+            print_package_graph(&mut dot, graph, &style)?;
+            write_graph_file(path, &dot)
         }
+    }
+}
+
+/// Write a `dot` graph to `path`.
+///
+/// Write `dot` as is if `path` is a dot file, and render it with Graphviz if
+/// `path` is an SVG, PNG or PDF file.
+fn write_graph_file(path: &Path, dot: &[u8]) -> std::io::Result<()> {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("dot" | "gv") => std::fs::write(path, dot),
+        Some(format @ ("svg" | "png" | "pdf")) => render_graph(path, format, dot),
+        _ => Err(usage_error(format!(
+            "Cannot write graph to {}: Expected a .dot, .gv, .svg, .png or .pdf file",
+            path.display()
+        ))),
+    }
+}
+
+/// Render a `dot` graph to `path` in `format` with Graphviz.
+fn render_graph(path: &Path, format: &str, dot: &[u8]) -> std::io::Result<()> {
+    let mut child = std::process::Command::new("dot")
+        .arg(format!("-T{format}"))
+        .arg("-o")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| {
+            if error.kind() == std::io::ErrorKind::NotFound {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!(
+                        "Graphviz is required to render {}, but dot was not found",
+                        path.display()
+                    ),
+                )
+            } else {
+                error
+            }
+        })?;
+    // Take stdin to close it after writing the graph, so that dot finishes.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(dot)?;
+    }
+    let status = child.wait()?;
+    if status.success() {
         Ok(())
+    } else {
+        Err(std::io::Error::other(format!(
+            "dot failed to render {}: {status}",
+            path.display()
+        )))
+    }
+}
+
+/// Print a list of orphans, according to `options`.
+///
+/// `orphan_graph` is the subgraph of all orphans.  Only print orphans which
+/// match `filter`.
+fn print_orphan_list<'a, W: Write, G>(
+    write: &mut W,
+    options: &args::Orphans,
+    orphan_graph: G,
+    pkg_graph: &AlpmDepGraphMap<'a>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()>
+where
+    G: IntoNodeIdentifiers<NodeId = PackageNode<'a>> + IntoNeighborsDirected,
+{
+    let style = options.graph_options.oneline_style(repositories);
+    let print_orphans = |write: &mut W, orphans: &[PackageNode<'a>]| {
+        for node in orphans {
+            if options.count_optional_dependents {
+                let optional_dependents = pkg_graph
+                    .edges_directed(*node, Direction::Incoming)
+                    .filter(|(_, _, edge)| **edge == DependencyEdge::Optional)
+                    .count();
+                print_package_one_line_annotated(
+                    write,
+                    node.package(),
+                    &style,
+                    format_args!("{optional_dependents} optional dependents"),
+                )?;
+            } else {
+                print_package_one_line(write, node.package(), &style)?;
+            }
+        }
+        Ok(())
+    };
+
+    let mut orphans = orphan_graph
+        .node_identifiers()
+        .filter(|node| filter.matches(node))
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+    #[cfg(feature = "interactive")]
+    if options.interactive && std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+        return select_orphans_for_removal(write, &orphans, &style);
+    }
+    if let style @ ListStyle::Tsv { .. } = options.graph_options.list_style(repositories) {
+        print_packages(write, orphans.iter().map(|node| node.package()), &style)
+    } else if options.split_dependencies {
+        // Orphans which no other orphan depends on are what `pacman -Qdt`
+        // lists; all other orphans are only installed because of these.
+        let (roots, dependencies): (Vec<_>, Vec<_>) = orphans.into_iter().partition(|node| {
+            orphan_graph
+                .neighbors_directed(*node, Direction::Incoming)
+                .next()
+                .is_none()
+        });
+        print_orphans(write, &roots)?;
+        if !dependencies.is_empty() {
+            print_group_header(write, "dependencies of orphans")?;
+            print_orphans(write, &dependencies)?;
+        }
+        Ok(())
+    } else if options.group_by_parent {
+        for (parent, nodes) in group_orphans_by_parent(orphan_graph, &orphans) {
+            print_group_header(write, parent)?;
+            print_orphans(write, &nodes)?;
+        }
+        Ok(())
+    } else if options.group_by_repo {
+        // Sort groups by repository name, but put foreign packages last
+        let mut groups = BTreeMap::<_, Vec<_>>::new();
+        for node in orphans {
+            let syncdb = repositories.find_syncdb(&node);
+            groups
+                .entry((syncdb.is_none(), syncdb.map_or("foreign", alpm::Db::name)))
+                .or_default()
+                .push(node);
+        }
+        for ((_, repository), nodes) in groups {
+            print_group_header(write, repository)?;
+            print_orphans(write, &nodes)?;
+        }
+        Ok(())
+    } else {
+        print_orphans(write, &orphans)
+    }
+}
+
+/// Group `orphans` by the nearest orphan which no other orphan depends on.
+///
+/// Return groups sorted by the name of their parent orphan; each group
+/// contains the parent itself first.
+fn group_orphans_by_parent<'a, G>(
+    orphan_graph: G,
+    orphans: &[PackageNode<'a>],
+) -> BTreeMap<&'a str, Vec<PackageNode<'a>>>
+where
+    G: IntoNeighborsDirected<NodeId = PackageNode<'a>>,
+{
+    let parents = orphans.iter().copied().filter(|node| {
+        orphan_graph
+            .neighbors_directed(*node, Direction::Incoming)
+            .next()
+            .is_none()
+    });
+    // Walk down from all parents at once, in order of their names, to
+    // attribute each orphan to its nearest parent.
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut owners = parents.map(|node| (node, node)).collect::<HashMap<_, _>>();
+    let mut queue = owners.keys().copied().collect::<Vec<_>>();
+    queue.sort_by_key(|node| node.package().name());
+    let mut queue = VecDeque::from(queue);
+    while let Some(node) = queue.pop_front() {
+        let owner = owners.get(&node).copied().unwrap_or(node);
+        for dependency in orphan_graph.neighbors_directed(node, Direction::Outgoing) {
+            if let Entry::Vacant(entry) = owners.entry(dependency) {
+                entry.insert(owner);
+                queue.push_back(dependency);
+            }
+        }
+    }
+    let mut groups = BTreeMap::<_, Vec<_>>::new();
+    for node in orphans {
+        // Orphans only reachable through cycles have no parent; they're their
+        // own group.
+        let owner = owners.get(node).copied().unwrap_or(*node);
+        groups
+            .entry(owner.package().name())
+            .or_default()
+            .push(*node);
+    }
+    for nodes in groups.values_mut() {
+        nodes.sort_by_key(|node| {
+            (
+                owners.get(node).is_none_or(|owner| owner != node),
+                node.package().name(),
+            )
+        });
+    }
+    groups
+}
+
+/// Let the user pick orphans to keep, and print a command to remove all others.
+///
+/// Never remove anything; only print the `pacman -Rns` command.
+#[cfg(feature = "interactive")]
+fn select_orphans_for_removal<W: Write>(
+    write: &mut W,
+    orphans: &[PackageNode<'_>],
+    style: &OneLineStyle<'_>,
+) -> std::io::Result<()> {
+    let width = orphans.len().to_string().len();
+    for (index, node) in orphans.iter().enumerate() {
+        write!(write, "{:>width$} ", index + 1)?;
+        print_package_one_line(write, node.package(), style)?;
+    }
+    let keep = loop {
+        write!(write, "Packages to keep (e.g. 1 3 5-7): ")?;
+        write.flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            // End of input, so the user aborted the selection.
+            return writeln!(write);
+        }
+        match parse_selection(&line, orphans.len()) {
+            Some(keep) => break keep,
+            None => print_warning(
+                &mut anstream::stderr().lock(),
+                format_args!("Invalid selection: {}", line.trim()),
+            )?,
+        }
+    };
+    let remove = orphans
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !keep.contains(&(index + 1)))
+        .map(|(_, node)| node.package().name())
+        .collect::<Vec<_>>();
+    if remove.is_empty() {
+        writeln!(write, "Nothing to remove")
+    } else {
+        writeln!(write, "pacman -Rns {}", remove.join(" "))
+    }
+}
+
+/// Parse a selection of numbers between 1 and `max`, e.g. `1 3 5-7`.
+///
+/// Return `None` if `selection` is invalid.
+#[cfg(feature = "interactive")]
+fn parse_selection(selection: &str, max: usize) -> Option<HashSet<usize>> {
+    let mut numbers = HashSet::new();
+    for item in selection
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|item| !item.is_empty())
+    {
+        let (start, end) = item.split_once('-').unwrap_or((item, item));
+        let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+        if start == 0 || max < end || end < start {
+            return None;
+        }
+        numbers.extend(start..=end);
+    }
+    Some(numbers)
+}
+
+/// Read the names of packages changed by the last transaction, if requested.
+///
+/// Return `None` unless `options` ask to only list packages changed by the
+/// last transaction.  Warn and return an empty set if the pacman log is not
+/// readable.
+fn last_transaction(
+    options: &args::GraphOptions,
+    alpm: &Alpm,
+) -> std::io::Result<Option<HashSet<String>>> {
+    if !options.since_upgrade {
+        return Ok(None);
     }
+    let path = alpm.logfile().unwrap_or("/var/log/pacman.log");
+    match File::open(path)
+        .map(BufReader::new)
+        .and_then(packit::log::last_transaction_packages)
+    {
+        Ok(packages) => Ok(Some(packages)),
+        Err(error) => {
+            print_warning(
+                &mut anstream::stderr().lock(),
+                format_args!("Failed to read pacman log {path}: {error}"),
+            )?;
+            Ok(Some(HashSet::new()))
+        }
+    }
+}
+
+/// Read a list of package names from `path`.
+///
+/// Expect one package per line, and ignore blank lines and comments after `#`.
+fn read_package_list(path: &Path) -> std::io::Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path).map_err(|error| {
+        std::io::Error::new(
+            error.kind(),
+            format!("Failed to read package list {}: {error}", path.display()),
+        )
+    })?;
+    Ok(contents
+        .lines()
+        .map(|line| line.split_once('#').map_or(line, |(line, _)| line).trim())
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Which packages to list.
+struct PackageFilter<'a> {
+    /// Only list packages with these names, if any.
+    changed: Option<HashSet<String>>,
+    /// Only list foreign packages if `true`, or native packages if `false`.
+    foreign: Option<bool>,
+    /// Only list packages with a newer version in a sync database.
+    upgradable: bool,
+    /// Only list packages with this install reason, if any.
+    reason: Option<PackageReason>,
+    /// Never list packages with these names.
+    excluded: HashSet<String>,
+    repositories: &'a Repositories<'a>,
+}
+
+impl<'a> PackageFilter<'a> {
+    fn new(
+        options: &args::GraphOptions,
+        alpm: &Alpm,
+        repositories: &'a Repositories<'a>,
+    ) -> std::io::Result<Self> {
+        Ok(Self {
+            changed: last_transaction(options, alpm)?,
+            foreign: options.foreign(),
+            upgradable: options.newer_version_available,
+            reason: options.reason.map(args::Reason::package_reason),
+            excluded: options.exclude.iter().cloned().collect(),
+            repositories,
+        })
+    }
+
+    /// Whether to list `package`.
+    fn matches(&self, package: &Pkg) -> bool {
+        self.changed
+            .as_ref()
+            .is_none_or(|changed| changed.contains(package.name()))
+            && self
+                .foreign
+                .is_none_or(|foreign| self.repositories.is_foreign(package) == foreign)
+            && (!self.upgradable || self.repositories.find_upgrade(package).is_some())
+            && self.reason.is_none_or(|reason| package.reason() == reason)
+            && !self.excluded.contains(package.name())
+    }
+}
+
+/// An error in the arguments given by the user, e.g. a package which is not
+/// installed.
+#[derive(Debug)]
+struct UsageError(Box<dyn std::error::Error + Send + Sync>);
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// Turn `error` into an I/O error which exits with [`EXIT_USAGE`].
+fn usage_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, UsageError(error.into()))
+}
+
+/// Turn a failure to find a package into an error.
+///
+/// Packages which are not installed are a usage error, to exit with
+/// [`EXIT_USAGE`].
+fn package_not_found(error: alpm::Error) -> std::io::Error {
+    usage_error(error)
+}
+
+/// Build the dependency graph for `localdb`, restricted to `roots` if any.
+///
+/// Only restrict the graph to dependencies of `roots` along edges for which
+/// `follows_edge` returns true, and omit all other edges.
+fn build_graph<'a>(
+    roots: &[String],
+    localdb: &'a alpm::Db,
+    follows_edge: impl Fn(PackageNode<'a>, DependencyEdge) -> bool,
+) -> std::io::Result<AlpmDepGraphMap<'a>> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    if roots.is_empty() {
+        Ok(pkg_graph)
+    } else {
+        let roots = roots
+            .iter()
+            .map(|name| localdb.pkg(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(package_not_found)?;
+        let followed_graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            follows_edge(edge.source(), *edge.weight())
+        });
+        Ok(packit::graph::collect_graph(
+            &packit::dependencies::dependencies(&followed_graph, &roots),
+        ))
+    }
+}
+
+/// Collect all orphans in `pkg_graph`, ignoring optional dependencies.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn required_orphans<'a>(pkg_graph: &AlpmDepGraphMap<'a>) -> HashSet<PackageNode<'a>> {
+    let required_graph =
+        EdgeFiltered::from_fn(pkg_graph, |edge| *edge.weight() == DependencyEdge::Required);
+    packit::dependencies::orphans(&required_graph)
+        .node_identifiers()
+        .collect()
+}
+
+/// Whether the orphan `node` is a strict orphan.
+///
+/// Strict orphans are orphans which no package optionally requires, not even
+/// another orphan.
+fn is_strict_orphan(pkg_graph: &AlpmDepGraphMap<'_>, node: PackageNode<'_>) -> bool {
+    pkg_graph
+        .edges_directed(node, Direction::Incoming)
+        .all(|(_, _, edge)| *edge == DependencyEdge::Required)
+}
+
+fn list_strict_orphans(
+    options: &args::Orphans,
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    let orphans = packit::dependencies::orphans(pkg_graph);
+    let strict_orphans = NodeFiltered::from_fn(&orphans, |node| is_strict_orphan(pkg_graph, node));
+
+    let mut stdout = print::stdout();
+    if options.graph_options.dot {
+        print_graph(
+            &mut stdout,
+            &strict_orphans,
+            &options.graph_options,
+            repositories,
+        )
+    } else {
+        print_orphan_list(
+            &mut stdout,
+            options,
+            &strict_orphans,
+            pkg_graph,
+            repositories,
+            filter,
+        )
+    }
+}
+
+/// Whether a dependency of `dependent` on `dependency` keeps `dependency`
+/// from being an orphan.
+fn keeps_dependency(
+    options: &args::Orphans,
+    dependent: PackageNode<'_>,
+    dependency: PackageNode<'_>,
+    kind: DependencyEdge,
+) -> bool {
+    options.graph_options.follows_edge(dependent, kind)
+        && !(options.exclude_optional_providers
+            && kind == DependencyEdge::Optional
+            && packit::dependencies::provided_dependency(
+                dependent.package(),
+                dependency.package(),
+                kind,
+            )
+            .is_some())
+}
+
+/// Print a short report of orphans for a pacman hook.
+fn print_hook_report(
+    options: &args::Orphans,
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    let graph = EdgeFiltered::from_fn(pkg_graph, |edge| {
+        keeps_dependency(options, edge.source(), edge.target(), *edge.weight())
+    });
+    let mut orphans = packit::dependencies::orphans(&graph)
+        .node_identifiers()
+        .filter(|node| filter.matches(node))
+        .collect::<Vec<_>>();
+    if orphans.is_empty() {
+        return Ok(());
+    }
+    orphans.sort_by_key(|node| node.package().name());
+    let mut stdout = std::io::stdout().lock();
+    match orphans.len() {
+        1 => writeln!(stdout, "1 orphan:")?,
+        count => writeln!(stdout, "{count} orphans:")?,
+    }
+    for node in orphans {
+        writeln!(stdout, "{}", node.name())?;
+    }
+    Ok(())
+}
+
+/// Print orphans like `pacman -Qdtq`.
+///
+/// pacman lists packages installed as dependencies which no installed package
+/// requires or optionally requires, regardless of whether these packages are
+/// orphans themselves.
+fn print_pacman_orphans(localdb: &alpm::Db) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let orphans = pacman_orphans(&pkg_graph);
+    let mut stdout = std::io::stdout().lock();
+    for node in orphans {
+        writeln!(stdout, "{}", node.name())?;
+    }
+    Ok(())
+}
+
+/// Find the orphans which `pacman -Qdtq` lists, sorted by name.
+fn pacman_orphans<'a>(pkg_graph: &AlpmDepGraphMap<'a>) -> Vec<PackageNode<'a>> {
+    let mut orphans = pkg_graph
+        .nodes()
+        .filter(|node| {
+            node.reason() == PackageReason::Depend
+                && pkg_graph
+                    .neighbors_directed(*node, Direction::Incoming)
+                    .next()
+                    .is_none()
+        })
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+    orphans
+}
+
+/// List orphans which `pacman -Qdtq` does not list.
+///
+/// Annotate every orphan with the orphans which require or optionally require
+/// it, and thus hide it from pacman.
+fn compare_pacman_orphans(
+    options: &args::Orphans,
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let listed_by_pacman = pacman_orphans(pkg_graph)
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let mut orphans = packit::dependencies::orphans(pkg_graph)
+        .node_identifiers()
+        .filter(|node| !listed_by_pacman.contains(node) && filter.matches(node))
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+    let style = options.graph_options.oneline_style(repositories);
+    let mut stdout = print::stdout();
+    for node in orphans {
+        let mut required_by = Vec::new();
+        let mut optional_for = Vec::new();
+        for (dependent, _, edge) in pkg_graph.edges_directed(node, Direction::Incoming) {
+            match edge {
+                DependencyEdge::Required => required_by.push(dependent.package().name()),
+                DependencyEdge::Optional => optional_for.push(dependent.package().name()),
+            }
+        }
+        required_by.sort_unstable();
+        optional_for.sort_unstable();
+        let reasons = [("required by", required_by), ("optional for", optional_for)]
+            .into_iter()
+            .filter(|(_, dependents)| !dependents.is_empty())
+            .map(|(reason, dependents)| format!("{reason} {}", dependents.join(", ")))
+            .collect::<Vec<_>>();
+        print_package_one_line_annotated(
+            &mut stdout,
+            node.package(),
+            &style,
+            format_args!("{}", reasons.join("; ")),
+        )?;
+    }
+    Ok(())
+}
+
+/// List orphans without building the dependency graph.
+fn list_orphans_fast(
+    options: &args::Orphans,
+    alpm: &Alpm,
+    repositories: &Repositories<'_>,
+) -> std::io::Result<()> {
+    let filter = PackageFilter::new(&options.graph_options, alpm, repositories)?;
+    let mut orphans = packit::dependencies::orphans_in_db(
+        alpm.localdb(),
+        options.graph_options.ignore_optdepends,
+    );
+    orphans.retain(|pkg| filter.matches(pkg));
+    orphans.sort_by_key(|pkg| pkg.name());
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        orphans,
+        &options.graph_options.list_style(repositories),
+    )
+}
+
+/// List packages which are only installed for optional dependencies.
+fn list_optional_only(
+    options: &args::Orphans,
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    let optional_only = packit::dependencies::optional_only(pkg_graph);
+    let mut stdout = print::stdout();
+    if !matches!(
+        options.graph_options.list_style(repositories),
+        ListStyle::Tsv { .. }
+    ) {
+        print_group_header(&mut stdout, "only optionally required")?;
+    }
+    print_orphan_list(
+        &mut stdout,
+        options,
+        &optional_only,
+        pkg_graph,
+        repositories,
+        filter,
+    )
+}
+
+/// List orphans, annotated with whether they are also orphans if optional
+/// dependencies are considered.
+fn annotate_orphans(
+    options: &args::Orphans,
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let optional_orphans = packit::dependencies::orphans(pkg_graph)
+        .node_identifiers()
+        .collect::<HashSet<_>>();
+    let mut orphans = required_orphans(pkg_graph)
+        .into_iter()
+        .filter(|node| filter.matches(node))
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|node| node.package().name());
+    let style = options.graph_options.oneline_style(repositories);
+    let mut stdout = print::stdout();
+    for node in orphans {
+        print_orphan_annotated(
+            &mut stdout,
+            node.package(),
+            &style,
+            optional_orphans.contains(&node),
+        )?;
+    }
+    Ok(())
+}
+
+fn report_orphans(
+    pkg_graph: &AlpmDepGraphMap<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()> {
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let optional_orphans = packit::dependencies::orphans(pkg_graph)
+        .node_identifiers()
+        .collect::<HashSet<_>>();
+    // Every orphan is also an orphan if optional dependencies are ignored, so
+    // the latter contains all candidates.
+    let mut rows = required_orphans(pkg_graph)
+        .into_iter()
+        .filter(|node| filter.matches(node))
+        .map(|node| {
+            let optional = optional_orphans.contains(&node);
+            (
+                node.package(),
+                optional,
+                optional && is_strict_orphan(pkg_graph, node),
+            )
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|(pkg, _, _)| pkg.name());
+    let mut stdout = print::stdout();
+    print_orphan_report(&mut stdout, &rows)
+}
+
+/// Collect the names of all base packages in `pkg_graph`.
+///
+/// Base packages are the `base` package, all packages in the `base` group, and
+/// all packages which these transitively require.  `base` used to be a group,
+/// but is a meta package on current Arch Linux, so look for both.
+fn base_packages(pkg_graph: &AlpmDepGraphMap<'_>) -> HashSet<String> {
+    let roots = pkg_graph
+        .nodes()
+        .map(PackageNode::package)
+        .filter(|pkg| pkg.name() == "base" || pkg.groups().iter().any(|group| group == "base"))
+        .collect::<Vec<_>>();
+    let required_graph =
+        EdgeFiltered::from_fn(pkg_graph, |edge| *edge.weight() == DependencyEdge::Required);
+    packit::dependencies::dependencies(&required_graph, &roots)
+        .node_identifiers()
+        .map(|node| node.name().to_string())
+        .collect()
 }
 
 fn orphans_command(options: &args::Orphans, alpm: &Alpm) -> std::io::Result<()> {
     let localdb = alpm.localdb();
-    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
-    if options.graph_options.ignore_optdepends {
+    let repositories = options.graph_options.repositories(alpm);
+    if options.graph_options.reason == Some(args::Reason::Explicit) {
+        return Err(usage_error(
+            "Orphans are never explicitly installed, --reason explicit would list nothing",
+        ));
+    }
+    if options.pacman_compat {
+        return print_pacman_orphans(localdb);
+    }
+    if options.fast {
+        return list_orphans_fast(options, alpm, &repositories);
+    }
+    let pkg_graph = build_graph(&options.graph_options.roots, localdb, |dependent, edge| {
+        options.graph_options.follows_edge(dependent, edge)
+    })?;
+    let mut filter = PackageFilter::new(&options.graph_options, alpm, &repositories)?;
+    if options.no_base {
+        filter.excluded.extend(base_packages(&pkg_graph));
+    }
+    if options.hook {
+        print_hook_report(options, &pkg_graph, &filter)
+    } else if options.compare_pacman {
+        compare_pacman_orphans(options, &pkg_graph, &repositories, &filter)
+    } else if options.report {
+        report_orphans(&pkg_graph, &filter)
+    } else if options.annotate {
+        annotate_orphans(options, &pkg_graph, &repositories, &filter)
+    } else if options.optional_only {
+        list_optional_only(options, &pkg_graph, &repositories, &filter)
+    } else if options.reverse_optional {
+        list_strict_orphans(options, &pkg_graph, &repositories, &filter)
+    } else if options.graph_options.filters_optdepends() || options.exclude_optional_providers {
         list_orphans(
             options,
             &EdgeFiltered::from_fn(&pkg_graph, |edge| {
-                *edge.weight() == DependencyEdge::Required
+                keeps_dependency(options, edge.source(), edge.target(), *edge.weight())
             }),
+            &pkg_graph,
+            &repositories,
+            &filter,
+        )
+    } else {
+        list_orphans(options, &pkg_graph, &pkg_graph, &repositories, &filter)
+    }
+}
+
+fn print_dependents<'a, G>(
+    options: &args::Dependents,
+    dependents: G,
+    packages: &[&'a Package],
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
+) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
+        + NodeIndexable
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>
+        + IntoEdgesDirected
+        + IntoNodeReferences
+        + IntoEdgeReferences,
+{
+    let mut stdout = print::stdout();
+    if options.graph_options.dot {
+        print_graph(
+            &mut stdout,
+            dependents,
+            &options.graph_options,
+            repositories,
         )
+    } else if options.tree {
+        let style = TreeStyle {
+            package: options.graph_options.oneline_style(repositories),
+            connectors: if options.ascii {
+                TreeConnectors::ASCII
+            } else {
+                TreeConnectors::UNICODE
+            },
+            print_root: !options.no_root,
+            optional_as_leaves: options.optional_as_tree_leaves,
+            max_depth: options.reverse_depth,
+            expand_provides: options.expand_provides_in_tree,
+        };
+        let dependents = NodeFiltered::from_fn(dependents, |node: PackageNode<'a>| {
+            packages
+                .iter()
+                .any(|package| std::ptr::eq(*package, node.package()))
+                || !options.graph_options.excludes(node.package())
+        });
+        for package in packages {
+            print_package_tree(
+                &mut stdout,
+                &dependents,
+                PackageNode::new(package),
+                Direction::Incoming,
+                &style,
+            )?;
+        }
+        Ok(())
+    } else if options.count_paths {
+        let style = options.graph_options.oneline_style(repositories);
+        for (node, paths) in packit::dependencies::dependent_path_counts(dependents, packages) {
+            if filter.matches(&node) {
+                print_package_one_line_annotated(
+                    &mut stdout,
+                    node.package(),
+                    &style,
+                    format_args!("{paths} paths"),
+                )?;
+            }
+        }
+        Ok(())
     } else {
-        list_orphans(options, &pkg_graph)
+        let style = options.graph_options.list_style(repositories);
+        let is_listed = |pkg: &&Package| {
+            !packages.iter().any(|package| std::ptr::eq(*pkg, *package)) && filter.matches(pkg)
+        };
+        if options.sort == Some(args::SortDependents::Depth) {
+            let nodes = packit::dependencies::dependent_distances(dependents, packages);
+            print_packages_in_order(
+                &mut stdout,
+                nodes
+                    .into_iter()
+                    .map(|(node, _)| node.package())
+                    .filter(is_listed),
+                &style,
+            )
+        } else {
+            print_packages(
+                &mut stdout,
+                dependents
+                    .node_identifiers()
+                    .map(PackageNode::package)
+                    .filter(is_listed),
+                &style,
+            )
+        }
     }
 }
 
 fn list_dependents<'a, G>(
     options: &args::Dependents,
     pkg_graph: G,
-    package: &'a Package,
+    packages: &[&'a Package],
+    repositories: &Repositories<'_>,
+    filter: &PackageFilter<'_>,
 ) -> std::io::Result<()>
 where
     G: GraphRef
@@ -109,64 +1011,1080 @@ where
         + Data<EdgeWeight = DependencyEdge, NodeWeight = PackageNode<'a>>
         + Visitable<NodeId = PackageNode<'a>>
         + NodeIndexable
-        + IntoNeighborsDirected
+        + IntoEdgesDirected
         + IntoNodeIdentifiers
         + IntoNodeReferences
         + IntoEdgeReferences,
 {
-    let mut stdout = anstream::stdout().lock();
-    let dependents = packit::dependencies::dependents(&pkg_graph, package);
-    if options.graph_options.dot {
-        print_package_graph(
-            &mut stdout,
-            &dependents,
-            options.graph_options.oneline_style(),
+    if options.direct {
+        print_dependents(
+            options,
+            &packit::dependencies::direct_dependents(&pkg_graph, packages),
+            packages,
+            repositories,
+            filter,
         )
     } else {
-        todo!()
+        print_dependents(
+            options,
+            &packit::dependencies::dependents(&pkg_graph, packages),
+            packages,
+            repositories,
+            filter,
+        )
     }
 }
 
 fn dependents_command(options: &args::Dependents, alpm: &Alpm) -> std::io::Result<()> {
     let localdb = alpm.localdb();
-    let source_pkg = localdb
-        .pkg(options.package.as_str())
-        .map_err(std::io::Error::other)?;
-    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let mut stderr = anstream::stderr().lock();
+    let mut source_pkgs = Vec::with_capacity(options.packages.len());
+    for name in &options.packages {
+        let package = localdb.pkg(name.as_str()).or_else(|error| {
+            if options.exact {
+                Err(error)
+            } else {
+                packit::alpm::find_package_ignore_case(localdb, name).ok_or(error)
+            }
+        });
+        match package {
+            Ok(pkg) => source_pkgs.push(pkg),
+            Err(error) => {
+                // If there's no package of the given name, look for packages
+                // which provide the name, e.g. a soname.
+                let providers = packit::alpm::find_providers(localdb, name);
+                if providers.is_empty() {
+                    if !options.quiet_errors {
+                        print_warning(&mut stderr, format_args!("{name}: {error}"))?;
+                    }
+                } else {
+                    source_pkgs.extend(providers);
+                }
+            }
+        }
+    }
+    if source_pkgs.is_empty() {
+        return Err(usage_error("None of the given packages is installed"));
+    }
+
+    let repositories = options.graph_options.repositories(alpm);
+    let pkg_graph = build_graph(&options.graph_options.roots, localdb, |dependent, edge| {
+        options.graph_options.follows_edge(dependent, edge)
+    })?;
+    let filter = PackageFilter::new(&options.graph_options, alpm, &repositories)?;
+
+    for source_pkg in &source_pkgs {
+        for (dependent, dependency) in
+            packit::dependencies::provided_dependents(localdb, source_pkg)
+        {
+            print_provided_dependency_warning(&mut stderr, source_pkg, dependent, dependency)?;
+        }
+    }
 
-    if options.graph_options.ignore_optdepends {
+    if options.graph_options.filters_optdepends() {
         list_dependents(
             options,
             &EdgeFiltered::from_fn(&pkg_graph, |edge| {
-                *edge.weight() == DependencyEdge::Required
+                options
+                    .graph_options
+                    .follows_edge(edge.source(), *edge.weight())
             }),
-            source_pkg,
+            &source_pkgs,
+            &repositories,
+            &filter,
+        )?;
+    } else {
+        list_dependents(options, &pkg_graph, &source_pkgs, &repositories, &filter)?;
+    }
+    if options.include_syncdbs {
+        print_sync_dependents(options, alpm, &source_pkgs, &repositories)?;
+    }
+    Ok(())
+}
+
+/// Print packages in sync databases which depend on any of `packages`.
+fn print_sync_dependents(
+    options: &args::Dependents,
+    alpm: &Alpm,
+    packages: &[&Package],
+    repositories: &Repositories<'_>,
+) -> std::io::Result<()> {
+    let syncdbs = alpm.syncdbs().iter().collect::<Vec<_>>();
+    let mut dependents = packages
+        .iter()
+        .flat_map(|package| {
+            packit::dependencies::sync_dependents(alpm.localdb(), &syncdbs, package)
+        })
+        .filter(|(_, pkg, kind)| {
+            options
+                .graph_options
+                .follows_edge(PackageNode::new(pkg), *kind)
+        })
+        .collect::<Vec<_>>();
+    dependents.sort_by_key(|(db, pkg, _)| (pkg.name(), db.name()));
+    dependents.dedup_by_key(|(_, pkg, _)| pkg.name());
+    let style = options.graph_options.oneline_style(repositories);
+    let mut stdout = print::stdout();
+    print_group_header(&mut stdout, "in sync databases")?;
+    for (db, pkg, kind) in dependents {
+        match kind {
+            DependencyEdge::Required => print_package_one_line_annotated(
+                &mut stdout,
+                pkg,
+                &style,
+                format_args!("{}", db.name()),
+            )?,
+            DependencyEdge::Optional => print_package_one_line_annotated(
+                &mut stdout,
+                pkg,
+                &style,
+                format_args!("{}, optional", db.name()),
+            )?,
+        }
+    }
+    Ok(())
+}
+
+fn search_command(options: &args::Search, alpm: &Alpm) -> std::io::Result<()> {
+    let repositories = options.display_options.repositories(alpm);
+    let matches = packit::alpm::search_packages(alpm.localdb(), &options.keyword);
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        matches,
+        &options.display_options.list_style(&repositories),
+    )
+}
+
+fn optdepends_command(options: &args::Optdepends, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let repositories = options.display_options.repositories(alpm);
+    let style = options.display_options.oneline_style(&repositories);
+    let mut packages = localdb
+        .pkgs()
+        .iter()
+        .filter(|pkg| pkg.reason() == PackageReason::Explicit && !pkg.optdepends().is_empty())
+        .collect::<Vec<_>>();
+    packages.sort_by_key(|pkg| pkg.name());
+    let mut stdout = print::stdout();
+    for package in packages {
+        let optdepends = packit::dependencies::optional_dependencies(localdb, package);
+        if options.missing && optdepends.iter().all(|(_, installed)| installed.is_some()) {
+            continue;
+        }
+        print_package_one_line(&mut stdout, package, &style)?;
+        for (dependency, installed) in optdepends {
+            if !options.missing || installed.is_none() {
+                print_dependency(&mut stdout, dependency, installed.is_some())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn redundant_command(options: &args::Redundant, alpm: &Alpm) -> std::io::Result<()> {
+    let repositories = options.display_options.repositories(alpm);
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let redundant = if options.with_optdepends {
+        packit::dependencies::redundant_explicit(&pkg_graph)
+    } else {
+        packit::dependencies::redundant_explicit(&EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            *edge.weight() == DependencyEdge::Required
+        }))
+    };
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        redundant.into_iter().map(PackageNode::package),
+        &options.display_options.list_style(&repositories),
+    )
+}
+
+/// Print all dependencies of the package in `path`, marked as installed or
+/// missing.
+fn check_package_file(path: &Path, alpm: &Alpm, style: &OneLineStyle<'_>) -> std::io::Result<()> {
+    let filename = path
+        .to_str()
+        .ok_or_else(|| usage_error(format!("Invalid package file name: {}", path.display())))?;
+    // We only read metadata, and never install the package, so do not
+    // check signatures.
+    let package = alpm
+        .pkg_load(filename, false, alpm::SigLevel::NONE)
+        .map_err(|error| usage_error(format!("{}: {error}", path.display())))?;
+    let pkgs = alpm.localdb().pkgs();
+    let mut stdout = print::stdout();
+    print_package_one_line(&mut stdout, &package, style)?;
+    for dependency in package.depends() {
+        let installed = pkgs.find_satisfier(dependency.to_string()).is_some();
+        print_dependency(&mut stdout, dependency, installed)?;
+    }
+    Ok(())
+}
+
+fn missing_command(options: &args::Missing, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let repositories = options.display_options.repositories(alpm);
+    let style = options.display_options.oneline_style(&repositories);
+    if let Some(path) = &options.package_file {
+        return check_package_file(path, alpm, &style);
+    }
+    let mut not_found = 0;
+    let mut packages = if options.packages.is_empty() {
+        localdb.pkgs().iter().collect::<Vec<_>>()
+    } else if options.keep_going {
+        let mut stderr = anstream::stderr().lock();
+        let mut packages = Vec::with_capacity(options.packages.len());
+        for name in &options.packages {
+            match localdb.pkg(name.as_str()) {
+                Ok(pkg) => packages.push(pkg),
+                Err(error) => {
+                    not_found += 1;
+                    print_warning(&mut stderr, format_args!("{name}: {error}"))?;
+                }
+            }
+        }
+        packages
+    } else {
+        options
+            .packages
+            .iter()
+            .map(|name| localdb.pkg(name.as_str()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(package_not_found)?
+    };
+    packages.sort_by_key(|pkg| pkg.name());
+    let mut stdout = print::stdout();
+    for package in packages {
+        let missing = packit::dependencies::missing_dependencies(localdb, package);
+        if !missing.is_empty() {
+            print_package_one_line(&mut stdout, package, &style)?;
+            for dependency in missing {
+                print_dependency(&mut stdout, dependency, false)?;
+            }
+        }
+    }
+    if not_found > 0 {
+        return Err(usage_error(format!(
+            "{not_found} of {} packages not installed",
+            options.packages.len()
+        )));
+    }
+    Ok(())
+}
+
+fn would_orphan_command(options: &args::WouldOrphan, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let package = localdb
+        .pkg(options.package.as_str())
+        .map_err(package_not_found)?;
+    let repositories = options.display_options.repositories(alpm);
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let required_graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+        *edge.weight() == DependencyEdge::Required
+    });
+    let mut stderr = anstream::stderr().lock();
+    let mut dependents = pkg_graph
+        .edges_directed(PackageNode::new(package), Direction::Incoming)
+        .filter(|(_, _, edge)| **edge == DependencyEdge::Required)
+        .map(|(dependent, _, _)| dependent)
+        .collect::<Vec<_>>();
+    dependents.sort_by_key(|node| node.package().name());
+    for dependent in dependents {
+        print_warning(
+            &mut stderr,
+            format_args!(
+                "{} requires {}, and breaks if it is removed",
+                dependent.name(),
+                package.name()
+            ),
+        )?;
+    }
+    let orphans = if options.ignore_optdepends {
+        packit::dependencies::removal_orphans(&required_graph, package)
+    } else {
+        packit::dependencies::removal_orphans(&pkg_graph, package)
+    };
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        orphans.into_iter().map(PackageNode::package),
+        &options.display_options.list_style(&repositories),
+    )
+}
+
+/// Collect all packages which `package` transitively requires.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn required_dependencies<'a>(
+    pkg_graph: &AlpmDepGraphMap<'a>,
+    package: &'a Package,
+) -> HashSet<PackageNode<'a>> {
+    let required_graph =
+        EdgeFiltered::from_fn(pkg_graph, |edge| *edge.weight() == DependencyEdge::Required);
+    packit::dependencies::dependencies(&required_graph, &[package])
+        .node_identifiers()
+        .collect()
+}
+
+fn compare_command(options: &args::Compare, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let repositories = options.display_options.repositories(alpm);
+    let first = localdb
+        .pkg(options.first.as_str())
+        .map_err(package_not_found)?;
+    let second = localdb
+        .pkg(options.second.as_str())
+        .map_err(package_not_found)?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut first_dependencies = required_dependencies(&pkg_graph, first);
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut second_dependencies = required_dependencies(&pkg_graph, second);
+    for node in [PackageNode::new(first), PackageNode::new(second)] {
+        first_dependencies.remove(&node);
+        second_dependencies.remove(&node);
+    }
+
+    let style = options.display_options.oneline_style(&repositories);
+    let mut stdout = print::stdout();
+    print_group_header(&mut stdout, &format!("only {}", first.name()))?;
+    print_packages_one_line(
+        &mut stdout,
+        first_dependencies
+            .difference(&second_dependencies)
+            .map(|node| node.package()),
+        &style,
+    )?;
+    print_group_header(&mut stdout, &format!("only {}", second.name()))?;
+    print_packages_one_line(
+        &mut stdout,
+        second_dependencies
+            .difference(&first_dependencies)
+            .map(|node| node.package()),
+        &style,
+    )?;
+    print_group_header(&mut stdout, "shared")?;
+    print_packages_one_line(
+        &mut stdout,
+        first_dependencies
+            .intersection(&second_dependencies)
+            .map(|node| node.package()),
+        &style,
+    )
+}
+
+/// Check the installed packages for problems.
+///
+/// Report unsatisfied dependencies as errors, and dependency cycles and
+/// foreign orphans as warnings, on stderr.  Return whether there were no
+/// errors.
+fn doctor_command(alpm: &Alpm) -> std::io::Result<bool> {
+    let localdb = alpm.localdb();
+    let repositories = Repositories::new(alpm, None);
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let mut stderr = anstream::stderr().lock();
+
+    let unsatisfied = packit::dependencies::unsatisfied_dependencies(localdb);
+    for (package, dependency) in &unsatisfied {
+        match localdb.pkg(dependency.name()) {
+            Ok(installed) => print_error(
+                &mut stderr,
+                format_args!(
+                    "{} requires {dependency}, but {} {} is installed",
+                    package.name(),
+                    installed.name(),
+                    installed.version()
+                ),
+            )?,
+            Err(_) => print_error(
+                &mut stderr,
+                format_args!(
+                    "{} requires {dependency}, which is not installed",
+                    package.name()
+                ),
+            )?,
+        }
+    }
+
+    let required_graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+        *edge.weight() == DependencyEdge::Required
+    });
+    let cycles = packit::dependencies::cycles(&required_graph);
+    for cycle in &cycles {
+        let names = cycle.iter().map(|node| node.name()).collect::<Vec<_>>();
+        print_warning(
+            &mut stderr,
+            format_args!("Dependency cycle between {}", names.join(", ")),
+        )?;
+    }
+
+    let mut foreign_orphans = packit::dependencies::orphans(&pkg_graph)
+        .node_identifiers()
+        .filter(|node| repositories.is_foreign(node))
+        .collect::<Vec<_>>();
+    foreign_orphans.sort_by_key(|node| node.package().name());
+    for node in &foreign_orphans {
+        print_warning(
+            &mut stderr,
+            format_args!("Foreign package {} is an orphan", node.name()),
+        )?;
+    }
+
+    if unsatisfied.is_empty() && cycles.is_empty() && foreign_orphans.is_empty() {
+        writeln!(print::stdout(), "No problems found")?;
+    }
+    Ok(unsatisfied.is_empty())
+}
+
+fn distances_command(options: &args::Distances, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let seed = localdb
+        .pkg(options.seed.as_str())
+        .map_err(package_not_found)?;
+    let pkg_graph = packit::graph::build_graph_for_localdb(localdb);
+    let required_graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+        *edge.weight() == DependencyEdge::Required
+    });
+    let mut stdout = std::io::stdout().lock();
+    for (node, distance) in packit::dependencies::dependency_distances(&required_graph, seed) {
+        writeln!(stdout, "{distance}\t{}", node.name())?;
+    }
+    Ok(())
+}
+
+fn paths_command(options: &args::Paths, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let repositories = options.graph_options.repositories(alpm);
+    let from = localdb
+        .pkg(options.from.as_str())
+        .map_err(package_not_found)?;
+    let to = localdb
+        .pkg(options.to.as_str())
+        .map_err(package_not_found)?;
+    let pkg_graph = build_graph(&options.graph_options.roots, localdb, |dependent, edge| {
+        options.graph_options.follows_edge(dependent, edge)
+    })?;
+    let graph = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+        options
+            .graph_options
+            .follows_edge(edge.source(), *edge.weight())
+    });
+    // Look for one more path, to tell whether there are more paths than the
+    // limit.
+    let mut paths =
+        packit::dependencies::dependency_paths(&graph, from, to, options.limit.saturating_add(1));
+    if options.limit < paths.len() {
+        paths.truncate(options.limit);
+        print_warning(
+            &mut anstream::stderr().lock(),
+            format_args!(
+                "Stopped after {} paths, use --max-paths to find more",
+                options.limit
+            ),
+        )?;
+    }
+    let mut stdout = print::stdout();
+    if options.graph_options.dot {
+        #[allow(
+            clippy::mutable_key_type,
+            reason = "We do not mutate the package pointer while traversing the graph"
+        )]
+        let edges = paths
+            .iter()
+            .flat_map(|path| {
+                path.windows(2)
+                    .filter_map(|pair| pair.first().zip(pair.last()))
+            })
+            .map(|(from, to)| (*from, *to))
+            .collect::<HashSet<_>>();
+        let path_edges = EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            edges.contains(&(edge.source(), edge.target()))
+        });
+        #[allow(
+            clippy::mutable_key_type,
+            reason = "We do not mutate the package pointer while traversing the graph"
+        )]
+        let nodes = paths.iter().flatten().copied().collect::<HashSet<_>>();
+        let path_graph = NodeFiltered::from_fn(&path_edges, |node| nodes.contains(&node));
+        print_graph(
+            &mut stdout,
+            &path_graph,
+            &options.graph_options,
+            &repositories,
+        )
+    } else {
+        for path in paths {
+            let names = path.iter().map(|node| node.name()).collect::<Vec<_>>();
+            writeln!(stdout, "{}", names.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+fn longest_chain_command(options: &args::LongestChain, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let chain = if options.ignore_optdepends {
+        packit::dependencies::longest_chain(&EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            *edge.weight() == DependencyEdge::Required
+        }))
+    } else {
+        packit::dependencies::longest_chain(&pkg_graph)
+    };
+    let names = chain.iter().map(|node| node.name()).collect::<Vec<_>>();
+    writeln!(std::io::stdout().lock(), "{}", names.join(" -> "))
+}
+
+fn providers_command(options: &args::Providers, alpm: &Alpm) -> std::io::Result<()> {
+    let repositories = options.graph_options.repositories(alpm);
+    let (mut providers, mut dependents) =
+        packit::dependencies::virtual_dependency(alpm.localdb(), &options.name);
+    providers.sort_by_key(|pkg| pkg.name());
+    dependents.sort_by_key(|(pkg, _)| pkg.name());
+    let mut stdout = print::stdout();
+    if options.graph_options.dot {
+        let style = options.graph_options.dot_style(&repositories);
+        let mut dot = Vec::new();
+        print_virtual_dependency_graph(&mut dot, &options.name, &providers, &dependents, &style)?;
+        match &options.graph_options.output_graph {
+            None => stdout.write_all(&dot),
+            Some(path) => write_graph_file(path, &dot),
+        }
+    } else {
+        let style = options.graph_options.oneline_style(&repositories);
+        print_group_header(&mut stdout, "providers")?;
+        print_packages_one_line(&mut stdout, providers, &style)?;
+        print_group_header(&mut stdout, "dependents")?;
+        for (pkg, kind) in dependents {
+            match kind {
+                DependencyEdge::Required => print_package_one_line(&mut stdout, pkg, &style)?,
+                DependencyEdge::Optional => print_package_one_line_annotated(
+                    &mut stdout,
+                    pkg,
+                    &style,
+                    format_args!("optional"),
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn stats_command(options: &args::Stats, alpm: &Alpm) -> std::io::Result<()> {
+    let localdb = alpm.localdb();
+    let pkg_graph = build_graph(&options.roots, localdb, |_, edge| {
+        !options.ignore_optdepends || edge == DependencyEdge::Required
+    })?;
+    let graph = if options.ignore_optdepends {
+        packit::graph::collect_graph(&EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            *edge.weight() == DependencyEdge::Required
+        }))
+    } else {
+        pkg_graph
+    };
+    let explicit = graph
+        .nodes()
+        .filter(|node| node.reason() == PackageReason::Explicit)
+        .count();
+    let required_edges = graph
+        .all_edges()
+        .filter(|(_, _, edge)| **edge == DependencyEdge::Required)
+        .count();
+    // Measure depth from the roots if any, and from all explicitly installed
+    // packages otherwise.
+    let depth = graph
+        .nodes()
+        .filter(|node| {
+            if options.roots.is_empty() {
+                node.reason() == PackageReason::Explicit
+            } else {
+                options.roots.iter().any(|root| root == node.name())
+            }
+        })
+        .flat_map(|node| packit::dependencies::dependency_distances(&graph, node.package()))
+        .map(|(_, distance)| distance)
+        .max()
+        .unwrap_or_default();
+    let orphans = packit::dependencies::orphans(&graph)
+        .node_identifiers()
+        .count();
+    let cycles = packit::dependencies::cycles(&graph).len();
+    let mut dependents = graph
+        .nodes()
+        .map(|node| {
+            let count = graph.neighbors_directed(node, Direction::Incoming).count();
+            (node, count)
+        })
+        .collect::<Vec<_>>();
+    dependents.sort_by_key(|(node, count)| (std::cmp::Reverse(*count), node.package().name()));
+
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "packages\t{}", graph.node_count())?;
+    writeln!(stdout, "explicit\t{explicit}")?;
+    writeln!(stdout, "dependencies\t{}", graph.node_count() - explicit)?;
+    writeln!(stdout, "required edges\t{required_edges}")?;
+    writeln!(
+        stdout,
+        "optional edges\t{}",
+        graph.edge_count() - required_edges
+    )?;
+    writeln!(stdout, "depth\t{depth}")?;
+    writeln!(stdout, "orphans\t{orphans}")?;
+    writeln!(stdout, "cycles\t{cycles}")?;
+    for (node, count) in dependents.into_iter().take(options.top_dependents) {
+        writeln!(stdout, "dependents of {}\t{count}", node.name())?;
+    }
+    Ok(())
+}
+
+fn central_command(options: &args::Central, alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let degrees = if options.ignore_optdepends {
+        packit::dependencies::degree_centrality(&EdgeFiltered::from_fn(&pkg_graph, |edge| {
+            *edge.weight() == DependencyEdge::Required
+        }))
+    } else {
+        packit::dependencies::degree_centrality(&pkg_graph)
+    };
+    let mut stdout = std::io::stdout().lock();
+    for (node, degree) in degrees.into_iter().take(options.top.unwrap_or(usize::MAX)) {
+        writeln!(stdout, "{degree}\t{}", node.name())?;
+    }
+    Ok(())
+}
+
+fn validate_command(options: &args::Validate, alpm: &Alpm) -> std::io::Result<()> {
+    let repositories = options.display_options.repositories(alpm);
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        packit::alpm::unvalidated_packages(alpm.localdb()),
+        &options.display_options.list_style(&repositories),
+    )
+}
+
+fn reinstall_candidates_command(
+    options: &args::ReinstallCandidates,
+    alpm: &Alpm,
+) -> std::io::Result<()> {
+    let repositories = options.display_options.repositories(alpm);
+    let candidates = packit::alpm::unvalidated_packages(alpm.localdb())
+        .filter(|pkg| !options.available || repositories.find_syncdb(pkg).is_some())
+        .collect::<Vec<_>>();
+    let mut stdout = print::stdout();
+    print_packages(
+        &mut stdout,
+        candidates.iter().copied(),
+        &options.display_options.list_style(&repositories),
+    )?;
+    if !candidates.is_empty() {
+        writeln!(
+            anstream::stderr().lock(),
+            "packit does not reinstall packages; use pacman -S to reinstall candidates"
+        )?;
+    }
+    Ok(())
+}
+
+fn disk_command(options: &args::Disk, alpm: &Alpm) -> std::io::Result<()> {
+    let repositories = options.display_options.repositories(alpm);
+    let mut stdout = print::stdout();
+    match options.aggregate_by {
+        Some(args::AggregateBy::Repo) => {
+            let mut sizes = BTreeMap::<_, i64>::new();
+            for pkg in alpm.localdb().pkgs() {
+                let repository = repositories
+                    .find_syncdb(pkg)
+                    .map_or("foreign", alpm::Db::name);
+                *sizes.entry(repository).or_default() += pkg.isize();
+            }
+            let mut sizes = sizes.into_iter().collect::<Vec<_>>();
+            sizes.sort_by_key(|(repository, size)| (std::cmp::Reverse(*size), *repository));
+            match options.display_options.format {
+                args::Format::Text => print_group_sizes(&mut stdout, &sizes),
+                args::Format::Tsv => {
+                    print_group_sizes_tsv(&mut stdout, &sizes, options.display_options.quiet == 0)
+                }
+            }
+        }
+        None => {
+            let mut packages = alpm.localdb().pkgs().into_iter().collect::<Vec<_>>();
+            packages.sort_by_key(|pkg| (std::cmp::Reverse(pkg.isize()), pkg.name()));
+            match options.display_options.format {
+                args::Format::Text => {
+                    let style = options.display_options.oneline_style(&repositories);
+                    print_packages_by_size(&mut stdout, packages, &style)
+                }
+                // The TSV format already has the size of every package.
+                args::Format::Tsv => print_packages_in_order(
+                    &mut stdout,
+                    packages,
+                    &options.display_options.list_style(&repositories),
+                ),
+            }
+        }
+    }
+}
+
+fn list_command(options: &args::List, alpm: &Alpm) -> std::io::Result<()> {
+    let graph_options = &options.graph_options;
+    let repositories = graph_options.repositories(alpm);
+    let pkg_graph = build_graph(&graph_options.roots, alpm.localdb(), |dependent, edge| {
+        graph_options.follows_edge(dependent, edge)
+    })?;
+    let filter = PackageFilter::new(graph_options, alpm, &repositories)?;
+    let mut stdout = print::stdout();
+    if graph_options.dot && graph_options.filters_optdepends() {
+        print_graph(
+            &mut stdout,
+            &EdgeFiltered::from_fn(&pkg_graph, |edge| {
+                graph_options.follows_edge(edge.source(), *edge.weight())
+            }),
+            graph_options,
+            &repositories,
         )
+    } else if graph_options.dot {
+        print_graph(&mut stdout, &pkg_graph, graph_options, &repositories)
+    } else if let Some(threshold) = options.depends_count {
+        let mut heavy = pkg_graph
+            .nodes()
+            .filter(|node| filter.matches(node))
+            .map(|node| {
+                let dependencies = pkg_graph
+                    .edges_directed(node, Direction::Outgoing)
+                    .filter(|(_, _, edge)| graph_options.follows_edge(node, **edge))
+                    .count();
+                (node, dependencies)
+            })
+            .filter(|(_, dependencies)| threshold < *dependencies)
+            .collect::<Vec<_>>();
+        heavy.sort_by_key(|(node, dependencies)| {
+            (std::cmp::Reverse(*dependencies), node.package().name())
+        });
+        let style = graph_options.oneline_style(&repositories);
+        for (node, dependencies) in heavy {
+            print_package_one_line_annotated(
+                &mut stdout,
+                node.package(),
+                &style,
+                format_args!("{dependencies} dependencies"),
+            )?;
+        }
+        Ok(())
     } else {
-        list_dependents(options, &pkg_graph, source_pkg)
+        print_packages(
+            &mut stdout,
+            pkg_graph
+                .nodes()
+                .map(PackageNode::package)
+                .filter(|pkg| filter.matches(pkg)),
+            &graph_options.list_style(&repositories),
+        )
+    }
+}
+
+/// Dump all edges of the dependency graph, for debugging.
+fn explain_edges_command(alpm: &Alpm) -> std::io::Result<()> {
+    let pkg_graph = packit::graph::build_graph_for_localdb(alpm.localdb());
+    let mut edges = pkg_graph
+        .all_edges()
+        .map(|(from, to, edge)| (from.package().name(), to.package().name(), *edge))
+        .collect::<Vec<_>>();
+    edges.sort_by_key(|(from, to, edge)| (*from, *to, *edge == DependencyEdge::Optional));
+    let mut stdout = std::io::stdout().lock();
+    for (from, to, edge) in edges {
+        writeln!(stdout, "{from} --{edge}--> {to}")?;
     }
+    Ok(())
 }
 
-fn main() -> std::io::Result<()> {
-    use alpm_utils::{alpm_with_conf, config::Config};
+/// Log to stderr, at `level` unless overridden by `$RUST_LOG`.
+fn setup_logging(level: LevelFilter) {
+    use tracing_subscriber::{filter::Targets, layer::SubscriberExt, util::SubscriberInitExt};
 
-    tracing_subscriber::fmt::init();
+    let targets = std::env::var("RUST_LOG")
+        .ok()
+        .and_then(|var| var.parse::<Targets>().ok())
+        .unwrap_or_else(|| Targets::new().with_default(level));
+    tracing_subscriber::fmt()
+        .with_max_level(LevelFilter::TRACE)
+        .with_writer(std::io::stderr)
+        .finish()
+        .with(targets)
+        .init();
+}
 
-    let args = CliArgs::parse();
+/// Exit code of a process killed by `SIGPIPE`, by shell convention.
+const EXIT_BROKEN_PIPE: i32 = 128 + 13;
+
+/// Exit code if `doctor` found problems.
+const EXIT_PROBLEMS: i32 = 1;
+
+/// Exit code for invalid input, e.g. packages which are not installed.
+const EXIT_USAGE: i32 = 2;
+
+/// Exit code for all other errors, e.g. failure to read the database.
+const EXIT_FAILURE: i32 = 3;
+
+/// Exit code if the command timed out, like `timeout(1)`.
+const EXIT_TIMEOUT: i32 = 124;
+
+/// Abort the process if it does not finish within `timeout`.
+///
+/// Call `on_timeout` right before aborting.  Return a sender which cancels the
+/// watchdog when dropped.
+fn start_watchdog(timeout: Duration, on_timeout: impl FnOnce() + Send + 'static) -> Sender<()> {
+    let (done, watchdog) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if watchdog.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+            // Exit with the timeout status even if we fail to print the error.
+            print_error(
+                &mut anstream::stderr().lock(),
+                format_args!("Timed out after {} seconds", timeout.as_secs()),
+            )
+            .unwrap_or_default();
+            on_timeout();
+            std::process::exit(EXIT_TIMEOUT);
+        }
+    });
+    done
+}
+
+fn open_alpm(config: &alpm_utils::config::Config) -> std::io::Result<Alpm> {
+    let alpm = alpm_utils::alpm_with_conf(config).map_err(std::io::Error::other)?;
+    alpm.set_log_cb((), packit::alpm::tracing_log_cb);
+    Ok(alpm)
+}
+
+/// Run `command`, and return its exit status.
+fn run_command(command: &args::Command, alpm: &Alpm) -> std::io::Result<i32> {
+    let result = match command {
+        args::Command::Orphans(orphans) => orphans_command(orphans, alpm),
+        args::Command::Dependents(dependents) => dependents_command(dependents, alpm),
+        args::Command::Search(search) => search_command(search, alpm),
+        args::Command::List(list) => list_command(list, alpm),
+        args::Command::Compare(compare) => compare_command(compare, alpm),
+        args::Command::Validate(validate) => validate_command(validate, alpm),
+        args::Command::Disk(disk) => disk_command(disk, alpm),
+        args::Command::Soname(soname) => {
+            let repositories = soname.display_options.repositories(alpm);
+            print_packages(
+                &mut print::stdout(),
+                packit::alpm::find_providers(alpm.localdb(), &soname.soname),
+                &soname.display_options.list_style(&repositories),
+            )
+        }
+        args::Command::ReinstallCandidates(candidates) => {
+            reinstall_candidates_command(candidates, alpm)
+        }
+        args::Command::Distances(distances) => distances_command(distances, alpm),
+        args::Command::Central(central) => central_command(central, alpm),
+        args::Command::Paths(paths) => paths_command(paths, alpm),
+        args::Command::LongestChain(options) => longest_chain_command(options, alpm),
+        args::Command::Providers(providers) => providers_command(providers, alpm),
+        args::Command::Stats(stats) => stats_command(stats, alpm),
+        args::Command::History => {
+            let months = packit::alpm::installs_per_month(alpm.localdb());
+            print_install_history(&mut print::stdout(), &months)
+        }
+        args::Command::Doctor => {
+            return doctor_command(alpm).map(|healthy| if healthy { 0 } else { EXIT_PROBLEMS });
+        }
+        args::Command::Optdepends(optdepends) => optdepends_command(optdepends, alpm),
+        args::Command::WouldOrphan(would_orphan) => would_orphan_command(would_orphan, alpm),
+        args::Command::Missing(missing) => missing_command(missing, alpm),
+        args::Command::Redundant(redundant) => redundant_command(redundant, alpm),
+        args::Command::ExplainEdges => explain_edges_command(alpm),
+        #[cfg(feature = "completions")]
+        args::Command::Completions(completions) => {
+            completions.print();
+            Ok(())
+        }
+    };
+    result.map(|()| 0)
+}
+
+/// How often to check whether the local database changed.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Run `command` again whenever the local database changes.
+///
+/// Clear the screen before every run.  Never returns unless an error occurs;
+/// Ctrl-C terminates the process.
+fn watch_command(
+    command: &args::Command,
+    config: &alpm_utils::config::Config,
+    mut alpm: Alpm,
+) -> std::io::Result<()> {
+    loop {
+        {
+            // Bypass anstream, which strips escape sequences if colors are
+            // disabled.
+            let mut stdout = std::io::stdout().lock();
+            write!(stdout, "\x1b[2J\x1b[H")?;
+            stdout.flush()?;
+        }
+        run_command(command, &alpm)?;
+        wait_for_localdb_change(Path::new(alpm.dbpath()))?;
+        // Open a new handle, because alpm caches the packages of the local
+        // database.
+        alpm = open_alpm(config)?;
+    }
+}
+
+/// Block until pacman changed the local database in `dbpath`.
+///
+/// pacman adds and removes a directory in the local database for every
+/// package it installs or removes, so watch the modification time of the local
+/// database directory.  Wait for pacman to release its lock, to only return
+/// after the transaction completed.
+///
+/// Poll rather than use inotify: one `stat` per second costs nothing, and
+/// pacman transactions take longer than that anyway.
+fn wait_for_localdb_change(dbpath: &Path) -> std::io::Result<()> {
+    let localdb = dbpath.join("local");
+    let lock = dbpath.join("db.lck");
+    let modified = std::fs::metadata(&localdb)?.modified()?;
+    loop {
+        std::thread::sleep(WATCH_INTERVAL);
+        if !lock.exists() && std::fs::metadata(&localdb)?.modified()? != modified {
+            return Ok(());
+        }
+    }
+}
+
+/// Get the exit code for `error`.
+fn exit_code(error: &std::io::Error) -> i32 {
+    if error.kind() == std::io::ErrorKind::BrokenPipe {
+        EXIT_BROKEN_PIPE
+    } else if matches!(error.get_ref(), Some(inner) if inner.is::<UsageError>()) {
+        EXIT_USAGE
+    } else {
+        EXIT_FAILURE
+    }
+}
+
+/// Print a one-line JSON summary of a command to `write`.
+///
+/// Include the number of lines the command printed to stdout.
+fn print_summary_json<W: Write>(
+    write: &mut W,
+    command: &str,
+    installed: usize,
+    exit_code: i32,
+    elapsed: Duration,
+) -> std::io::Result<()> {
+    writeln!(
+        write,
+        "{{\"command\":\"{command}\",\"installed\":{installed},\"lines\":{},\"exit_code\":{exit_code},\"elapsed_ms\":{}}}",
+        print::stdout_lines(),
+        elapsed.as_millis()
+    )
+}
+
+/// Open alpm and run the command in `args`, and return its exit status.
+///
+/// Set `installed` to the number of installed packages once alpm is open.
+fn open_and_run(
+    args: &mut CliArgs,
+    command: &str,
+    start: Instant,
+    installed: &mut usize,
+) -> std::io::Result<i32> {
+    use alpm_utils::config::Config;
 
     let config = Config::new().map_err(|error| match error.kind {
         alpm_utils::config::ErrorKind::Io(error) => error,
         _ => std::io::Error::new(std::io::ErrorKind::InvalidData, error),
     })?;
-    let alpm = alpm_with_conf(&config).map_err(std::io::Error::other)?;
-    alpm.set_log_cb((), packit::alpm::tracing_log_cb);
+    if let Some(options) = args.command.graph_options_mut() {
+        if let Some(path) = &options.include_file {
+            let roots = read_package_list(path)?;
+            options.roots.extend(roots);
+        }
+        if let Some(path) = &options.exclude_file {
+            let excluded = read_package_list(path)?;
+            options.exclude.extend(excluded);
+        }
+    }
+    let alpm = open_alpm(&config)?;
+    *installed = alpm.localdb().pkgs().len();
+    let summary = args.summary_json.then(|| (command.to_string(), *installed));
+    let _watchdog = args.timeout().map(|timeout| {
+        start_watchdog(timeout, move || {
+            if let Some((command, installed)) = summary {
+                print_summary_json(
+                    &mut std::io::stderr().lock(),
+                    &command,
+                    installed,
+                    EXIT_TIMEOUT,
+                    start.elapsed(),
+                )
+                .unwrap_or_default();
+            }
+        })
+    });
 
-    match args.command {
-        args::Command::Orphans(orphans) => orphans_command(&orphans, &alpm)?,
-        args::Command::Dependents(dependents) => dependents_command(&dependents, &alpm)?,
-        #[cfg(feature = "completions")]
-        args::Command::Completions(completions) => completions.print(),
+    if args.command.watch() {
+        return watch_command(&args.command, &config, alpm).map(|()| 0);
     }
+    run_command(&args.command, &alpm)
+}
 
-    Ok(())
+/// Run the command in `args`, and return its exit status.
+///
+/// Print a summary if requested, regardless of how the command exited.
+fn run(mut args: CliArgs, command: &str) -> std::io::Result<i32> {
+    let start = Instant::now();
+    let mut installed = 0;
+    let result = open_and_run(&mut args, command, start, &mut installed);
+    if args.summary_json {
+        print_summary_json(
+            &mut std::io::stderr().lock(),
+            command,
+            installed,
+            result.as_ref().map_or_else(exit_code, |status| *status),
+            start.elapsed(),
+        )
+        .unwrap_or_else(|error| tracing::warn!("Failed to print summary: {error}"));
+    }
+    result
+}
+
+fn main() {
+    let matches = CliArgs::command().get_matches();
+    let mut args = CliArgs::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let command = matches.subcommand_name().unwrap_or_default();
+    setup_logging(args.log_level());
+    if args.machine_readable {
+        CliArgs::check_machine_readable(&matches).unwrap_or_else(|error| error.exit());
+        anstream::ColorChoice::Never.write_global();
+        args.apply_machine_readable();
+    }
+    if args.explain_output {
+        let description = args.describe_output(command);
+        writeln!(anstream::stderr().lock(), "{description}").unwrap_or_default();
+    }
+
+    match run(args, command) {
+        Ok(0) => {}
+        Ok(status) => std::process::exit(status),
+        // The reader closed stdout, e.g. `packit orphans | head`; exit
+        // silently like a process killed by SIGPIPE.
+        Err(error) if error.kind() == std::io::ErrorKind::BrokenPipe => {
+            std::process::exit(EXIT_BROKEN_PIPE)
+        }
+        Err(error) => {
+            print_error(&mut anstream::stderr().lock(), format_args!("{error}"))
+                .unwrap_or_default();
+            std::process::exit(exit_code(&error))
+        }
+    }
 }
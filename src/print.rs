@@ -6,14 +6,26 @@
 
 //! Utilities for printing packages.
 
-use std::io::prelude::*;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    io::prelude::*,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
+use alpm::{Db, Dep, Package, PackageReason, PackageValidation, Pkg};
 use anstyle::{AnsiColor, Reset, Style};
-use packit::graph::{DependencyEdge, PackageNode};
+use packit::{
+    alpm::{Repositories, version_components},
+    dependencies::{is_required_and_optional, provided_dependency},
+    graph::{DependencyEdge, PackageNode, collect_graph, transitive_reduction},
+};
 use petgraph::{
+    Direction,
+    algo::condensation,
     dot::{Config, Dot, RankDir},
     visit::{
-        Data, EdgeRef, GraphProp, IntoEdgeReferences, IntoNodeReferences, NodeIndexable, NodeRef,
+        Data, EdgeFiltered, EdgeRef, GraphBase, GraphProp, IntoEdgeReferences, IntoEdgesDirected,
+        IntoNodeIdentifiers, IntoNodeReferences, NodeFiltered, NodeIndexable, NodeRef,
     },
 };
 
@@ -26,66 +38,1515 @@ pub enum PrintOneLine {
     WithVersion,
 }
 
+/// Which part of a package version to print.
+#[derive(Debug, Copy, Clone)]
+pub enum VersionFormat {
+    /// Print the full version, e.g. `2:1.0-3`.
+    Full,
+    /// Print the version without epoch, e.g. `1.0-3`.
+    NoEpoch,
+    /// Print only the `pkgver`, e.g. `1.0`.
+    Pkgver,
+}
+
+impl VersionFormat {
+    /// Format `version` in this format.
+    fn apply(self, version: &str) -> &str {
+        let (epoch, pkgver, _) = version_components(version);
+        match self {
+            VersionFormat::Full => version,
+            VersionFormat::NoEpoch => epoch.map_or(version, |epoch| {
+                version.get(epoch.len() + 1..).unwrap_or(version)
+            }),
+            VersionFormat::Pkgver => pkgver,
+        }
+    }
+}
+
+/// How to print a package on one line.
+#[derive(Debug, Copy, Clone)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "Display options are independent bools"
+)]
+pub struct OneLineStyle<'a> {
+    /// What to print.
+    pub what: PrintOneLine,
+    /// Whether to print the version in color.
+    pub color_version: bool,
+    /// Which part of the version to print.
+    pub version_format: VersionFormat,
+    /// Repositories to hyperlink package names to their Arch Linux package
+    /// page, if any.
+    pub hyperlinks: Option<&'a Repositories<'a>>,
+    /// Whether to print the architecture of the package.
+    pub with_arch: bool,
+    /// Whether to print how pacman validated the package.
+    pub with_validation: bool,
+    /// Whether to print the licenses of the package.
+    pub with_license: bool,
+    /// A string to print before the package, if any.
+    pub prefix: Option<&'a str>,
+    /// Widths to pad columns to, to align packages in a list.
+    pub columns: Option<Columns>,
+}
+
+/// Widths of columns of packages.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Columns {
+    /// The width of package names.
+    pub name: usize,
+    /// The width of package versions.
+    pub version: usize,
+    /// The width of package architectures.
+    pub arch: usize,
+}
+
+impl Columns {
+    /// Compute the widths of columns for `packages`.
+    fn of<'a>(packages: &[&'a alpm::Package], style: &OneLineStyle<'_>) -> Self {
+        let max_width = |width: fn(&'a alpm::Package) -> usize| {
+            packages
+                .iter()
+                .copied()
+                .map(width)
+                .max()
+                .unwrap_or_default()
+        };
+        Self {
+            name: max_width(|pkg| pkg.name().len()),
+            version: match style.what {
+                PrintOneLine::NameOnly => 0,
+                PrintOneLine::WithVersion => packages
+                    .iter()
+                    .map(|pkg| style.version_format.apply(pkg.version().as_str()).len())
+                    .max()
+                    .unwrap_or_default(),
+            },
+            arch: if style.with_arch {
+                max_width(|pkg| pkg.arch().map_or(0, str::len))
+            } else {
+                0
+            },
+        }
+    }
+
+    /// The total width of all columns, including separators.
+    fn total(&self) -> usize {
+        [self.name, self.version, self.arch]
+            .into_iter()
+            .filter(|width| 0 < *width)
+            .map(|width| width + 1)
+            .sum::<usize>()
+            .saturating_sub(1)
+    }
+}
+
+/// Pad text of length `len` to `width`.
+fn pad<W: Write>(write: &mut W, width: usize, len: usize) -> std::io::Result<()> {
+    write!(write, "{:1$}", "", width.saturating_sub(len))
+}
+
+/// How to print a package graph as dot.
+#[derive(Debug, Copy, Clone)]
+#[allow(
+    clippy::struct_excessive_bools,
+    reason = "Dot options are independent bools"
+)]
+pub struct DotStyle<'a> {
+    /// How to label package nodes.
+    pub label: PrintOneLine,
+    /// Whether to label package nodes with their plain name only.
+    pub plain_labels: bool,
+    /// Repositories of packages.
+    pub repositories: &'a Repositories<'a>,
+    /// Whether to add `repo-<name>` classes to package nodes.
+    pub repository_classes: bool,
+    /// Whether to link package nodes to their Arch Linux package page.
+    pub links: bool,
+    /// Whether to print a strict graph, without duplicate edges.
+    pub strict: bool,
+    /// Whether to shape package nodes by their install reason.
+    pub shape_by_reason: bool,
+    /// Whether to draw package nodes as records instead of HTML-like labels.
+    pub record_nodes: bool,
+    /// Whether to give required edges a higher weight than optional edges.
+    pub weight_required: bool,
+    /// Whether to color required edges which are also optional.
+    pub merge_optional_required: bool,
+    /// The dot style of edges for required dependencies.
+    pub required_edges_style: &'static str,
+    /// The dot style of edges for optional dependencies.
+    pub optional_edges_style: &'static str,
+    /// Whether to only print the transitive reduction of the graph.
+    pub transitive_reduction: bool,
+    /// Whether to collapse every dependency cycle into a single node.
+    pub condensation: bool,
+    /// Whether to add nodes for provided names, e.g. virtual packages.
+    pub virtual_nodes: bool,
+    /// Whether to show the number of direct dependencies of packages.
+    pub show_degree: bool,
+    /// Whether to add edges to packages which packages replace.
+    pub replaces: bool,
+    /// A repository to collapse into a single node, if any.
+    pub collapse_repository: Option<&'a str>,
+    /// The background color of the graph, if any.
+    pub background: Option<&'a str>,
+    /// The font for all text in the graph, if any.
+    pub font: Option<&'a str>,
+    /// Whether to report progress on stderr while printing the graph.
+    pub progress: bool,
+}
+
+impl DotStyle<'_> {
+    /// The font face for HTML-like labels.
+    fn font_face(&self) -> &str {
+        self.font.unwrap_or("sans-serif")
+    }
+}
+
+/// Print a package name, with an OSC 8 hyperlink to `url` if any.
+fn print_name<W: Write>(write: &mut W, name: &str, url: Option<&str>) -> std::io::Result<()> {
+    match url {
+        Some(url) => write!(write, "\x1b]8;;{url}\x1b\\{name}\x1b]8;;\x1b\\"),
+        None => write!(write, "{name}"),
+    }
+}
+
+/// Print a package, without a trailing newline.
+fn print_package<W: Write>(
+    write: &mut W,
+    package: &Pkg,
+    style: &OneLineStyle<'_>,
+) -> Result<(), std::io::Error> {
+    if let Some(prefix) = style.prefix {
+        write!(write, "{prefix}")?;
+    }
+    let url = style
+        .hyperlinks
+        .and_then(|repositories| repositories.package_url(package));
+    // Only pad columns which are followed by further columns, to avoid
+    // trailing whitespace.
+    let columns = style.columns.unwrap_or_default();
+    let after_version = style.with_arch || style.with_validation || style.with_license;
+    match style.what {
+        PrintOneLine::NameOnly => {
+            print_name(write, package.name(), url.as_deref())?;
+            if after_version {
+                pad(write, columns.name, package.name().len())?;
+            }
+        }
+        PrintOneLine::WithVersion => {
+            let bold = Style::new().bold();
+            let version_style = if style.color_version {
+                bold.fg_color(Some(AnsiColor::Green.into()))
+            } else {
+                bold
+            };
+            write!(write, "{bold}")?;
+            print_name(write, package.name(), url.as_deref())?;
+            pad(write, columns.name, package.name().len())?;
+            let version = style.version_format.apply(package.version().as_str());
+            write!(write, " {version_style}{version}{Reset}")?;
+            if after_version {
+                pad(write, columns.version, version.len())?;
+            }
+        }
+    }
+    if style.with_arch {
+        let arch = package.arch().unwrap_or_default();
+        if !arch.is_empty() || 0 < columns.arch {
+            write!(write, " {arch}")?;
+        }
+        if style.with_validation || style.with_license {
+            pad(write, columns.arch, arch.len())?;
+        }
+    }
+    if style.with_validation {
+        write!(
+            write,
+            " [{}]",
+            validation_names(package.validation()).join(", ")
+        )?;
+    }
+    if style.with_license {
+        write!(write, " {}", licenses(package))?;
+    }
+    Ok(())
+}
+
+/// Join the licenses of `package` with `/`.
+fn licenses(package: &Pkg) -> String {
+    package.licenses().iter().collect::<Vec<_>>().join("/")
+}
+
+/// Get human-readable names for a package validation.
+fn validation_names(validation: PackageValidation) -> Vec<&'static str> {
+    if validation.is_empty() {
+        return vec!["unknown"];
+    }
+    [
+        (PackageValidation::NONE, "none"),
+        (PackageValidation::MD5SUM, "MD5"),
+        (PackageValidation::SHA256SUM, "SHA-256"),
+        (PackageValidation::SIGNATURE, "signature"),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| validation.contains(*flag))
+    .map(|(_, name)| name)
+    .collect()
+}
+
 /// Print a package on one single line.
 pub fn print_package_one_line<W: Write>(
+    write: &mut W,
+    package: &Pkg,
+    style: &OneLineStyle<'_>,
+) -> Result<(), std::io::Error> {
+    print_package(write, package, style)?;
+    writeln!(write)
+}
+
+/// Print a package on one single line, followed by a dimmed `annotation`.
+pub fn print_package_one_line_annotated<W: Write>(
     write: &mut W,
     package: &alpm::Package,
-    how: PrintOneLine,
+    style: &OneLineStyle<'_>,
+    annotation: std::fmt::Arguments<'_>,
 ) -> Result<(), std::io::Error> {
-    match how {
-        PrintOneLine::NameOnly => writeln!(write, "{}", package.name()),
-        PrintOneLine::WithVersion => {
-            let bold = Style::new().bold();
-            let green = bold.fg_color(Some(AnsiColor::Green.into()));
-            writeln!(
+    let dimmed = Style::new().dimmed();
+    print_package(write, package, style)?;
+    writeln!(write, " {dimmed}({annotation}){Reset}")
+}
+
+/// Print an orphan on one single line, annotated with its kind of orphan.
+///
+/// Annotate orphans both with and without optional dependencies with `[R,O]`,
+/// and orphans only without optional dependencies with `[R]`.
+pub fn print_orphan_annotated<W: Write>(
+    write: &mut W,
+    package: &alpm::Package,
+    style: &OneLineStyle<'_>,
+    with_optional: bool,
+) -> Result<(), std::io::Error> {
+    if with_optional {
+        let red = Style::new().fg_color(Some(AnsiColor::Red.into()));
+        write!(write, "{red}[R,O]{Reset} ")?;
+    } else {
+        let yellow = Style::new().fg_color(Some(AnsiColor::Yellow.into()));
+        write!(write, "{yellow}[R]{Reset}   ")?;
+    }
+    print_package(write, package, style)?;
+    writeln!(write)
+}
+
+/// Connectors to draw trees with.
+#[derive(Debug, Copy, Clone)]
+pub struct TreeConnectors {
+    /// Connect a child which has further siblings.
+    pub branch: &'static str,
+    /// Connect the last child.
+    pub last_branch: &'static str,
+    /// Indent below a child which has further siblings.
+    pub vertical: &'static str,
+    /// Indent below the last child.
+    pub space: &'static str,
+}
+
+impl TreeConnectors {
+    /// Draw trees with Unicode box drawing characters.
+    pub const UNICODE: Self = Self {
+        branch: "├── ",
+        last_branch: "└── ",
+        vertical: "│   ",
+        space: "    ",
+    };
+
+    /// Draw trees with plain ASCII characters.
+    pub const ASCII: Self = Self {
+        branch: "|-- ",
+        last_branch: "`-- ",
+        vertical: "|   ",
+        space: "    ",
+    };
+}
+
+/// How to print a tree of packages.
+#[derive(Debug, Copy, Clone)]
+pub struct TreeStyle<'a> {
+    /// How to print each package.
+    pub package: OneLineStyle<'a>,
+    /// Connectors to draw the tree with.
+    pub connectors: TreeConnectors,
+    /// Whether to print the root of the tree.
+    ///
+    /// If `false` print the children of the root as top-level entries.
+    pub print_root: bool,
+    /// Whether to print optional dependencies without their children.
+    pub optional_as_leaves: bool,
+    /// How many levels below the root to print at most, if limited.
+    pub max_depth: Option<usize>,
+    /// Whether to show provided names through which packages depend on
+    /// each other.
+    pub expand_provides: bool,
+}
+
+/// Print trees of packages.
+struct TreePrinter<'s, 'a, G> {
+    graph: G,
+    direction: Direction,
+    style: &'s TreeStyle<'s>,
+    /// Packages whose children were already printed.
+    expanded: HashSet<PackageNode<'a>>,
+}
+
+impl<'a, G> TreePrinter<'_, 'a, G>
+where
+    G: IntoEdgesDirected<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge>,
+{
+    /// Get all children of `node` with the kind of their edge, sorted by name.
+    fn sorted_children(&self, node: PackageNode<'a>) -> Vec<(PackageNode<'a>, DependencyEdge)> {
+        let mut children = self
+            .graph
+            .edges_directed(node, self.direction)
+            .map(|edge| match self.direction {
+                Direction::Outgoing => (edge.target(), *edge.weight()),
+                Direction::Incoming => (edge.source(), *edge.weight()),
+            })
+            .collect::<Vec<_>>();
+        children.sort_by_key(|(child, _)| child.package().name());
+        children
+    }
+
+    /// Get the provided name through which the edge from `node` to `child`
+    /// goes, if any and if the style asks for it.
+    fn provided_name(
+        &self,
+        node: PackageNode<'a>,
+        child: PackageNode<'a>,
+        edge: DependencyEdge,
+    ) -> Option<&'a str> {
+        if !self.style.expand_provides {
+            return None;
+        }
+        let (dependent, provider) = match self.direction {
+            Direction::Outgoing => (node, child),
+            Direction::Incoming => (child, node),
+        };
+        provided_dependency(dependent.package(), provider.package(), edge).map(Dep::name)
+    }
+
+    /// Print `node` on a line, with all `notes` as annotation.
+    fn print_line<W: Write>(
+        &self,
+        write: &mut W,
+        node: PackageNode<'a>,
+        notes: &[&str],
+    ) -> Result<(), std::io::Error> {
+        if notes.is_empty() {
+            print_package_one_line(write, node.package(), &self.style.package)
+        } else {
+            print_package_one_line_annotated(
                 write,
-                "{bold}{} {green}{}{Reset}",
-                package.name(),
-                package.version()
+                node.package(),
+                &self.style.package,
+                format_args!("{}", notes.join(", ")),
             )
         }
     }
+
+    /// Print `node` at `depth` and its children, unless already expanded.
+    ///
+    /// If `via` is given, note that `node` was reached through this provided
+    /// name.
+    fn print_node<W: Write>(
+        &mut self,
+        write: &mut W,
+        node: PackageNode<'a>,
+        via: Option<&str>,
+        prefix: &str,
+        depth: usize,
+    ) -> Result<(), std::io::Error> {
+        let via = via.map(|name| format!("via {name}"));
+        let mut notes = via.as_deref().into_iter().collect::<Vec<_>>();
+        if self
+            .style
+            .max_depth
+            .is_some_and(|max_depth| max_depth <= depth)
+        {
+            // Do not mark the package as expanded, to still expand it where
+            // it's less deep.
+            self.print_line(write, node, &notes)
+        } else if self.expanded.insert(node) {
+            self.print_line(write, node, &notes)?;
+            self.print_children(write, node, prefix, depth)
+        } else {
+            notes.push("see above");
+            self.print_line(write, node, &notes)
+        }
+    }
+
+    fn print_children<W: Write>(
+        &mut self,
+        write: &mut W,
+        node: PackageNode<'a>,
+        prefix: &str,
+        depth: usize,
+    ) -> Result<(), std::io::Error> {
+        let connectors = self.style.connectors;
+        let children = self.sorted_children(node);
+        let last_index = children.len().saturating_sub(1);
+        for (index, (child, edge)) in children.into_iter().enumerate() {
+            let (branch, indent) = if index == last_index {
+                (connectors.last_branch, connectors.space)
+            } else {
+                (connectors.branch, connectors.vertical)
+            };
+            write!(write, "{prefix}{branch}")?;
+            let via = self.provided_name(node, child, edge);
+            if self.style.optional_as_leaves && edge == DependencyEdge::Optional {
+                // Do not mark the package as expanded, to still expand it
+                // where it's a required dependency.
+                let via = via.map(|name| format!("via {name}"));
+                let mut notes = vec!["optional"];
+                notes.extend(via.as_deref());
+                self.print_line(write, child, &notes)?;
+            } else {
+                self.print_node(write, child, via, &format!("{prefix}{indent}"), depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the tree of packages reachable from `root` in `direction`.
+///
+/// Print `root` and then recursively all its neighbors in `direction`, sorted
+/// by name.  Expand every package only once; print further occurrences of a
+/// package without their children.
+pub fn print_package_tree<'a, W: Write, G>(
+    write: &mut W,
+    graph: G,
+    root: PackageNode<'a>,
+    direction: Direction,
+    style: &TreeStyle<'_>,
+) -> Result<(), std::io::Error>
+where
+    G: IntoEdgesDirected<NodeId = PackageNode<'a>> + Data<EdgeWeight = DependencyEdge>,
+{
+    let mut printer = TreePrinter {
+        graph,
+        direction,
+        style,
+        expanded: HashSet::new(),
+    };
+    if style.print_root {
+        printer.print_node(write, root, None, "", 0)
+    } else {
+        printer.expanded.insert(root);
+        for (child, edge) in printer.sorted_children(root) {
+            let via = printer.provided_name(root, child, edge);
+            printer.print_node(write, child, via, "", 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print a header for a group of packages.
+pub fn print_group_header<W: Write>(write: &mut W, group: &str) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    writeln!(write, "{bold}== {group} =={Reset}")
+}
+
+/// How to print a list of packages.
+#[derive(Debug, Copy, Clone)]
+pub enum ListStyle<'a> {
+    /// Print each package on one single line.
+    OneLine(OneLineStyle<'a>),
+    /// Print each package on one single line, aligned in columns.
+    ///
+    /// Do not align packages if the columns would not fit into `max_width`.
+    Aligned {
+        /// How to print each package.
+        style: OneLineStyle<'a>,
+        /// The maximum width for aligned columns.
+        max_width: Option<usize>,
+    },
+    /// Print tab-separated values of name, version, installed size and
+    /// repository.
+    Tsv {
+        /// Repositories of packages.
+        repositories: &'a Repositories<'a>,
+        /// Whether to print a header line.
+        header: bool,
+        /// Whether to add a column for the architecture.
+        with_arch: bool,
+        /// Whether to add a column for the licenses.
+        with_license: bool,
+    },
+}
+
+/// Print packages sorted by name, in the given `style`.
+pub fn print_packages<'a, W: Write>(
+    write: &mut W,
+    packages: impl IntoIterator<Item = &'a alpm::Package>,
+    style: &ListStyle<'_>,
+) -> Result<(), std::io::Error> {
+    let mut packages = packages.into_iter().collect::<Vec<_>>();
+    packages.sort_by_key(|pkg| pkg.name());
+    print_packages_in_order(write, packages, style)
+}
+
+/// Print packages in the given order and `style`.
+pub fn print_packages_in_order<'a, W: Write>(
+    write: &mut W,
+    packages: impl IntoIterator<Item = &'a alpm::Package>,
+    style: &ListStyle<'_>,
+) -> Result<(), std::io::Error> {
+    match style {
+        ListStyle::OneLine(style) => {
+            for pkg in packages {
+                print_package_one_line(write, pkg, style)?;
+            }
+            Ok(())
+        }
+        ListStyle::Aligned { style, max_width } => {
+            let packages = packages.into_iter().collect::<Vec<_>>();
+            let columns = Columns::of(&packages, style);
+            let style = OneLineStyle {
+                columns: max_width
+                    .is_none_or(|max_width| columns.total() <= max_width)
+                    .then_some(columns),
+                ..*style
+            };
+            for pkg in packages {
+                print_package_one_line(write, pkg, &style)?;
+            }
+            Ok(())
+        }
+        ListStyle::Tsv {
+            repositories,
+            header,
+            with_arch,
+            with_license,
+        } => print_packages_tsv(
+            write,
+            packages,
+            repositories,
+            *header,
+            *with_arch,
+            *with_license,
+        ),
+    }
+}
+
+/// Print packages as tab-separated values.
+///
+/// Print the name, the version, the installed size in bytes, and the
+/// repository of each package, or `foreign` if the package is not in any
+/// repository.  If `with_arch` is true, add the architecture of each package,
+/// or an empty field if the package has none.  If `with_license` is true, add
+/// the licenses of each package, separated by `/`.  If `header` is true, print
+/// a header line first.
+fn print_packages_tsv<'a, W: Write>(
+    write: &mut W,
+    packages: impl IntoIterator<Item = &'a alpm::Package>,
+    repositories: &Repositories<'_>,
+    header: bool,
+    with_arch: bool,
+    with_license: bool,
+) -> Result<(), std::io::Error> {
+    if header {
+        write!(write, "name\tversion\tsize\trepo")?;
+        if with_arch {
+            write!(write, "\tarch")?;
+        }
+        if with_license {
+            write!(write, "\tlicense")?;
+        }
+        writeln!(write)?;
+    }
+    for pkg in packages {
+        let repository = repositories.find_syncdb(pkg).map_or("foreign", Db::name);
+        write!(
+            write,
+            "{}\t{}\t{}\t{repository}",
+            pkg.name(),
+            pkg.version(),
+            pkg.isize()
+        )?;
+        if with_arch {
+            write!(write, "\t{}", pkg.arch().unwrap_or_default())?;
+        }
+        if with_license {
+            write!(write, "\t{}", licenses(pkg))?;
+        }
+        writeln!(write)?;
+    }
+    Ok(())
+}
+
+/// Print packages sorted by name, each on one single line.
+pub fn print_packages_one_line<'a, W: Write>(
+    write: &mut W,
+    packages: impl IntoIterator<Item = &'a alpm::Package>,
+    style: &OneLineStyle<'_>,
+) -> Result<(), std::io::Error> {
+    let mut packages = packages.into_iter().collect::<Vec<_>>();
+    // Sort alphabetically
+    packages.sort_by_key(|pkg| pkg.name());
+    for pkg in packages {
+        print_package_one_line(write, pkg, style)?;
+    }
+    Ok(())
+}
+
+/// Print a report of orphans as an aligned table.
+///
+/// Each row has a package which is an orphan if optional dependencies are
+/// ignored, whether it is also an orphan if optional dependencies are
+/// considered, and whether it is a strict orphan, i.e. an orphan which no
+/// package optionally requires.
+pub fn print_orphan_report<W: Write>(
+    write: &mut W,
+    rows: &[(&alpm::Package, bool, bool)],
+) -> Result<(), std::io::Error> {
+    const PACKAGE: &str = "PACKAGE";
+    const WITH_OPTIONAL: &str = "WITH-OPTIONAL";
+    let bold = Style::new().bold();
+    let yes = Style::new().fg_color(Some(AnsiColor::Green.into()));
+    let no = Style::new().fg_color(Some(AnsiColor::Red.into()));
+    let width = rows
+        .iter()
+        .map(|(pkg, _, _)| pkg.name().len())
+        .chain(std::iter::once(PACKAGE.len()))
+        .max()
+        .unwrap_or_default();
+    writeln!(
+        write,
+        "{bold}{PACKAGE:width$}  {WITH_OPTIONAL}  STRICT{Reset}"
+    )?;
+    for (pkg, with_optional, strict) in rows {
+        let format_flag = |flag: bool| {
+            if flag {
+                format!("{yes}{:3}{Reset}", "yes")
+            } else {
+                format!("{no}{:3}{Reset}", "no")
+            }
+        };
+        writeln!(
+            write,
+            "{:width$}  {}{:pad$}  {}",
+            pkg.name(),
+            format_flag(*with_optional),
+            "",
+            format_flag(*strict),
+            pad = WITH_OPTIONAL.len() - 3,
+        )?;
+    }
+    Ok(())
+}
+
+/// Print a dependency, marked as installed or missing.
+pub fn print_dependency<W: Write>(
+    write: &mut W,
+    dependency: &alpm::Dep,
+    installed: bool,
+) -> Result<(), std::io::Error> {
+    if installed {
+        let green = Style::new().fg_color(Some(AnsiColor::Green.into()));
+        writeln!(write, "  [{green}✓{Reset}] {dependency}")
+    } else {
+        writeln!(write, "  [ ] {dependency}")
+    }
+}
+
+/// Print a warning `message`.
+pub fn print_warning<W: Write>(
+    write: &mut W,
+    message: std::fmt::Arguments<'_>,
+) -> Result<(), std::io::Error> {
+    let yellow = Style::new().bold().fg_color(Some(AnsiColor::Yellow.into()));
+    writeln!(write, "{yellow}warning:{Reset} {message}")
+}
+
+/// Print an error `message`.
+pub fn print_error<W: Write>(
+    write: &mut W,
+    message: std::fmt::Arguments<'_>,
+) -> Result<(), std::io::Error> {
+    let red = Style::new().bold().fg_color(Some(AnsiColor::Red.into()));
+    writeln!(write, "{red}error:{Reset} {message}")
+}
+
+/// Print the number of installed packages per month as a histogram.
+///
+/// `months` maps years and months to the number of explicitly installed
+/// packages and packages installed as dependencies.  Draw explicitly installed
+/// packages in bold, and dependencies dimmed.
+pub fn print_install_history<W: Write>(
+    write: &mut W,
+    months: &BTreeMap<(i64, i64), (usize, usize)>,
+) -> Result<(), std::io::Error> {
+    const MAX_BAR: usize = 50;
+    let explicit_style = Style::new().bold().fg_color(Some(AnsiColor::Green.into()));
+    let depend_style = Style::new().dimmed();
+    let max_total = months
+        .values()
+        .map(|(explicit, depend)| explicit + depend)
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    let bar = |count: usize| "█".repeat((count * MAX_BAR).div_ceil(max_total));
+    for ((year, month), (explicit, depend)) in months {
+        writeln!(
+            write,
+            "{year:04}-{month:02} {explicit:>5} explicit {depend:>5} dependencies {explicit_style}{}{Reset}{depend_style}{}{Reset}",
+            bar(*explicit),
+            bar(*depend),
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the installed size of packages as human-readable size.
+///
+/// Print every package with its size, in the given order.
+pub fn print_packages_by_size<'a, W: Write>(
+    write: &mut W,
+    packages: impl IntoIterator<Item = &'a Package>,
+    style: &OneLineStyle<'_>,
+) -> Result<(), std::io::Error> {
+    for package in packages {
+        print_package_one_line_annotated(
+            write,
+            package,
+            style,
+            format_args!("{}", human_size(package.isize())),
+        )?;
+    }
+    Ok(())
+}
+
+/// Print the total installed size of every group in `sizes`.
+///
+/// Print groups in the given order, e.g. `extra: 1.1 GiB`.
+pub fn print_group_sizes<W: Write>(write: &mut W, sizes: &[(&str, i64)]) -> std::io::Result<()> {
+    let bold = Style::new().bold();
+    for (group, size) in sizes {
+        writeln!(write, "{bold}{group}{Reset}: {}", human_size(*size))?;
+    }
+    Ok(())
+}
+
+/// Print the total installed size of every group in `sizes` as tab-separated
+/// values.
+///
+/// Print groups in the given order, each with its size in bytes.  If `header`
+/// is true, print a header line first.
+pub fn print_group_sizes_tsv<W: Write>(
+    write: &mut W,
+    sizes: &[(&str, i64)],
+    header: bool,
+) -> std::io::Result<()> {
+    if header {
+        writeln!(write, "repo\tsize")?;
+    }
+    for (group, size) in sizes {
+        writeln!(write, "{group}\t{size}")?;
+    }
+    Ok(())
+}
+
+/// Warn that `dependent` needs `dependency`, which is provided by `package`.
+pub fn print_provided_dependency_warning<W: Write>(
+    write: &mut W,
+    package: &alpm::Package,
+    dependent: &alpm::Package,
+    dependency: &alpm::Dep,
+) -> Result<(), std::io::Error> {
+    let bold = Style::new().bold();
+    print_warning(
+        write,
+        format_args!(
+            "{bold}{}{Reset} depends on {bold}{dependency}{Reset} provided by {bold}{}{Reset}, and breaks if {} is removed",
+            dependent.name(),
+            package.name(),
+            package.name(),
+        ),
+    )
+}
+
+/// Escape `s` for use inside a double-quoted dot string.
+fn escape_dot_string(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape `s` for use as text in an HTML-like dot label.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escape `s` for use in a dot record label.
+fn escape_record_field(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut escaped, c| {
+            if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+}
+
+/// Format `bytes` as human-readable size with binary units.
+fn human_size(bytes: i64) -> String {
+    let mut tenths = bytes.saturating_mul(10);
+    let mut unit = "B";
+    for next_unit in ["KiB", "MiB", "GiB", "TiB"] {
+        if tenths < 10240 {
+            break;
+        }
+        tenths /= 1024;
+        unit = next_unit;
+    }
+    format!("{}.{} {unit}", tenths / 10, tenths % 10)
+}
+
+/// How many lines to write between progress reports.
+const PROGRESS_INTERVAL: usize = 1000;
+
+/// A writer which reports the number of lines written to stderr.
+struct ProgressWriter<'w, W: Write> {
+    inner: &'w mut W,
+    lines: usize,
+    total: usize,
+}
+
+impl<W: Write> ProgressWriter<'_, W> {
+    /// Flush the graph, and clear the progress report.
+    fn finish(self) -> std::io::Result<()> {
+        self.inner.flush()?;
+        let mut stderr = std::io::stderr().lock();
+        write!(stderr, "\r\x1b[K")?;
+        stderr.flush()
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let before = self.lines;
+        self.lines += buf
+            .iter()
+            .take(written)
+            .filter(|byte| **byte == b'\n')
+            .count();
+        if before / PROGRESS_INTERVAL != self.lines / PROGRESS_INTERVAL {
+            let mut stderr = std::io::stderr().lock();
+            write!(
+                stderr,
+                "\rWriting graph: {}/{} lines",
+                self.lines, self.total
+            )?;
+            stderr.flush()?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Lines written through [`stdout`] so far.
+static STDOUT_LINES: AtomicUsize = AtomicUsize::new(0);
+
+/// A writer which counts the lines written to it in [`STDOUT_LINES`].
+pub struct LineCountingWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Write for LineCountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        let lines = buf
+            .iter()
+            .take(written)
+            .filter(|byte| **byte == b'\n')
+            .count();
+        STDOUT_LINES.fetch_add(lines, Ordering::Relaxed);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Lock stdout for printing results.
+///
+/// Count the lines written, for [`stdout_lines`].
+pub fn stdout() -> LineCountingWriter<anstream::AutoStream<std::io::StdoutLock<'static>>> {
+    LineCountingWriter {
+        inner: anstream::stdout().lock(),
+    }
+}
+
+/// The number of lines printed to [`stdout`] so far.
+pub fn stdout_lines() -> usize {
+    STDOUT_LINES.load(Ordering::Relaxed)
 }
 
 /// Print a package graph as dot.
+///
+/// If `style` asks for progress, report the number of lines written to
+/// stderr.
 pub fn print_package_graph<'a, G, W: Write>(
     write: &mut W,
     graph: G,
-    format: PrintOneLine,
+    style: &DotStyle<'_>,
+) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>
+        + IntoNodeReferences
+        + NodeIndexable,
+{
+    if style.progress {
+        // Roughly one line per node and per edge
+        let total = graph.node_references().count() + graph.edge_references().count();
+        let mut progress = ProgressWriter {
+            inner: write,
+            lines: 0,
+            total,
+        };
+        print_package_graph_content(&mut progress, graph, style)?;
+        progress.finish()
+    } else {
+        print_package_graph_content(write, graph, style)
+    }
+}
+
+/// Print a package graph as dot, optionally transitively reduced.
+fn print_package_graph_content<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    style: &DotStyle<'_>,
+) -> std::io::Result<()>
+where
+    G: GraphProp
+        + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>
+        + IntoNodeReferences
+        + NodeIndexable,
+{
+    if style.condensation {
+        print_condensation(write, graph, style)
+    } else if style.transitive_reduction {
+        let reduced = transitive_reduction(&collect_graph(graph));
+        print_dot(write, &reduced, style)
+    } else {
+        print_dot(write, graph, style)
+    }
+}
+
+/// Print the providers and dependents of a virtual `name` as dot.
+///
+/// Put a diamond node for `name` in the center, with `dependents` as ellipses
+/// on its left and `providers` as boxes on its right.
+pub fn print_virtual_dependency_graph<W: Write>(
+    write: &mut W,
+    name: &str,
+    providers: &[&Package],
+    dependents: &[(&Package, DependencyEdge)],
+    style: &DotStyle<'_>,
+) -> std::io::Result<()> {
+    let strict = if style.strict { "strict " } else { "" };
+    writeln!(write, "{strict}digraph {{")?;
+    writeln!(write, "    rankdir = LR")?;
+    print_graph_attributes(write, style)?;
+    let label = if style.plain_labels {
+        format!("\"{}\"", escape_dot_string(name))
+    } else {
+        format!(
+            "<<FONT FACE=\"{}\"><I>{}</I></FONT>>",
+            style.font_face(),
+            escape_html(name)
+        )
+    };
+    writeln!(write, "    virtual [ label = {label}, shape = diamond ]")?;
+    for (index, (package, kind)) in dependents.iter().enumerate() {
+        writeln!(
+            write,
+            "    dependent{index} [ shape = ellipse, {} ]",
+            node_label(package, None, style)
+        )?;
+        writeln!(
+            write,
+            "    dependent{index} -> virtual [ {} ]",
+            edge_attributes(*kind, style)
+        )?;
+    }
+    for (index, package) in providers.iter().enumerate() {
+        writeln!(
+            write,
+            "    provider{index} [ shape = box, {} ]",
+            node_label(package, None, style)
+        )?;
+        writeln!(write, "    virtual -> provider{index} [ style = dotted ]")?;
+    }
+    writeln!(write, "}}")
+}
+
+/// Print the condensation of a package graph as dot.
+///
+/// Collapse every dependency cycle into a single node labelled with all its
+/// packages, and print the resulting acyclic graph.  Merge edges between the
+/// same nodes into one edge, which is required if any of the merged edges is.
+fn print_condensation<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    style: &DotStyle<'_>,
 ) -> std::io::Result<()>
+where
+    G: Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
+        + IntoEdgeReferences
+        + IntoNodeIdentifiers<NodeId = PackageNode<'a>>,
+{
+    let condensed = condensation(collect_graph(graph).into_graph::<u32>(), true);
+    let strict = if style.strict { "strict " } else { "" };
+    writeln!(write, "{strict}digraph {{")?;
+    print_graph_attributes(write, style)?;
+    for index in condensed.node_indices() {
+        let mut members = condensed[index].clone();
+        members.sort_by_key(|node| node.package().name());
+        let attributes = match members.as_slice() {
+            [member] => node_label(member.package(), None, style),
+            members if style.plain_labels => {
+                let names = members.iter().map(|node| escape_dot_string(node.name()));
+                format!(
+                    "shape = box, label = \"{}\"",
+                    names.collect::<Vec<_>>().join("\\n")
+                )
+            }
+            members => {
+                let names = members.iter().map(|node| node.name());
+                format!(
+                    "shape = box, label = <<FONT FACE=\"{}\">{}</FONT>>",
+                    style.font_face(),
+                    names.collect::<Vec<_>>().join("<BR/>")
+                )
+            }
+        };
+        writeln!(write, "    {} [ {attributes} ]", index.index())?;
+    }
+    let mut edges = BTreeMap::new();
+    for edge in condensed.edge_references() {
+        let kind = edges
+            .entry((edge.source().index(), edge.target().index()))
+            .or_insert(*edge.weight());
+        if *edge.weight() == DependencyEdge::Required {
+            *kind = DependencyEdge::Required;
+        }
+    }
+    for ((from, to), kind) in edges {
+        writeln!(
+            write,
+            "    {from} -> {to} [ {} ]",
+            edge_attributes(kind, style)
+        )?;
+    }
+    writeln!(write, "}}")
+}
+
+/// Get dot attributes to label the node of `package`.
+///
+/// Add the number of direct dependencies to the label if `degree` is given.
+fn node_label(package: &Package, degree: Option<usize>, style: &DotStyle<'_>) -> String {
+    if style.plain_labels {
+        return format!("label = \"{}\"", escape_dot_string(package.name()));
+    }
+    if style.record_nodes {
+        let degree = degree.map_or_else(String::new, |degree| format!("|{degree} dependencies"));
+        return format!(
+            "shape = record, label = \"{{{}|{}|{}{degree}}}\"",
+            escape_record_field(package.name()),
+            escape_record_field(package.version().as_str()),
+            human_size(package.isize())
+        );
+    }
+    let degree = degree.map_or_else(String::new, |degree| {
+        format!("<BR/><FONT POINT-SIZE=\"10\">{degree} dependencies</FONT>")
+    });
+    let font = style.font_face();
+    match style.label {
+        PrintOneLine::NameOnly => format!(
+            "label = <<FONT FACE=\"{font}\">{}{degree}</FONT>>",
+            package.name()
+        ),
+        PrintOneLine::WithVersion => format!(
+            "label = <<FONT FACE=\"{font}\"><B>{name} <FONT COLOR=\"green\">{version}</FONT></B>{degree}</FONT>>",
+            name = package.name(),
+            version = package.version()
+        ),
+    }
+}
+
+/// Get dot attributes for an edge of the given `kind`.
+fn edge_attributes(kind: DependencyEdge, style: &DotStyle<'_>) -> String {
+    match kind {
+        // Graphviz keeps edges with a higher weight shorter and straighter;
+        // the default weight is 1.
+        DependencyEdge::Required if style.weight_required => {
+            format!("style = {}, weight = 10", style.required_edges_style)
+        }
+        DependencyEdge::Required => format!("style = {}", style.required_edges_style),
+        DependencyEdge::Optional => format!("style = {}", style.optional_edges_style),
+    }
+}
+
+/// Print a single node for all `collapsed` packages of `repository`.
+///
+/// Connect the node to all packages which depend on or are dependencies of
+/// any collapsed package.  Draw at most one edge of each direction between
+/// the node and a package, and prefer required over optional edges.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn print_collapsed_repository<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    repository: &str,
+    collapsed: &HashSet<PackageNode<'a>>,
+    style: &DotStyle<'_>,
+) -> std::io::Result<()>
+where
+    G: GraphBase<NodeId = PackageNode<'a>>
+        + IntoEdgeReferences<EdgeWeight = DependencyEdge>
+        + NodeIndexable,
+{
+    let id = format!("\"repository-{}\"", escape_dot_string(repository));
+    writeln!(
+        write,
+        "    {id} [ label = <<FONT FACE=\"{}\"><B>{}</B></FONT>>, shape = box3d ]",
+        style.font_face(),
+        escape_html(repository)
+    )?;
+    // Map (package index, whether the edge points into the repository) to
+    // the kind of the edge.
+    let mut edges = BTreeMap::new();
+    for edge in graph.edge_references() {
+        let key = match (
+            collapsed.contains(&edge.source()),
+            collapsed.contains(&edge.target()),
+        ) {
+            (false, true) => (graph.to_index(edge.source()), true),
+            (true, false) => (graph.to_index(edge.target()), false),
+            _ => continue,
+        };
+        let kind = edges.entry(key).or_insert(*edge.weight());
+        if *edge.weight() == DependencyEdge::Required {
+            *kind = DependencyEdge::Required;
+        }
+    }
+    for ((index, into_repository), kind) in edges {
+        if into_repository {
+            writeln!(
+                write,
+                "    {index} -> {id} [ {} ]",
+                edge_attributes(kind, style)
+            )?;
+        } else {
+            writeln!(
+                write,
+                "    {id} -> {index} [ {} ]",
+                edge_attributes(kind, style)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// An edge to a package through a name the package provides.
+type ProvidedEdge<'a> = (PackageNode<'a>, &'a str, PackageNode<'a>, DependencyEdge);
+
+/// Find all edges in `graph` which depend on a name the target provides.
+fn provided_edges<'a, G>(graph: G) -> Vec<ProvidedEdge<'a>>
+where
+    G: GraphBase<NodeId = PackageNode<'a>> + IntoEdgeReferences<EdgeWeight = DependencyEdge>,
+{
+    graph
+        .edge_references()
+        .filter_map(|edge| {
+            let dependency = provided_dependency(
+                edge.source().package(),
+                edge.target().package(),
+                *edge.weight(),
+            )?;
+            Some((
+                edge.source(),
+                dependency.name(),
+                edge.target(),
+                *edge.weight(),
+            ))
+        })
+        .collect()
+}
+
+/// Print a node for every provided name in `edges`.
+///
+/// Route all `edges` through these nodes.
+fn print_virtual_nodes<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    edges: Vec<ProvidedEdge<'a>>,
+    style: &DotStyle<'_>,
+) -> std::io::Result<()>
+where
+    G: NodeIndexable<NodeId = PackageNode<'a>>,
+{
+    let names = edges
+        .iter()
+        .map(|(_, name, _, _)| *name)
+        .collect::<BTreeSet<_>>();
+    for name in names {
+        writeln!(
+            write,
+            "    \"virtual-{}\" [ label = <<FONT FACE=\"{}\"><I>{}</I></FONT>>, shape = diamond ]",
+            escape_dot_string(name),
+            style.font_face(),
+            escape_html(name)
+        )?;
+    }
+    let mut provides = BTreeSet::new();
+    for (from, name, to, kind) in edges {
+        writeln!(
+            write,
+            "    {} -> \"virtual-{}\" [ {} ]",
+            graph.to_index(from),
+            escape_dot_string(name),
+            edge_attributes(kind, style)
+        )?;
+        provides.insert((name, graph.to_index(to)));
+    }
+    for (name, to) in provides {
+        writeln!(
+            write,
+            "    \"virtual-{}\" -> {to} [ style = dotted ]",
+            escape_dot_string(name),
+        )?;
+    }
+    Ok(())
+}
+
+/// Print an edge from every package in `graph` to every package it replaces.
+///
+/// Point to the node of the replaced package if it is in `graph`, and to a
+/// dashed node for the obsolete package otherwise.  Skip `collapsed` packages.
+fn print_replaces<'a, G, W: Write>(
+    write: &mut W,
+    graph: G,
+    collapsed: &HashSet<PackageNode<'a>>,
+    style: &DotStyle<'_>,
+) -> std::io::Result<()>
+where
+    G: Data<NodeWeight = PackageNode<'a>>
+        + IntoNodeReferences
+        + NodeIndexable<NodeId = PackageNode<'a>>,
+{
+    let nodes = graph
+        .node_references()
+        .map(|node| *node.weight())
+        .filter(|node| !collapsed.contains(node))
+        .collect::<Vec<_>>();
+    let by_name = nodes
+        .iter()
+        .map(|node| (node.package().name(), *node))
+        .collect::<HashMap<_, _>>();
+    let mut obsolete = BTreeSet::new();
+    for node in &nodes {
+        for replaced in node.package().replaces() {
+            let target = if let Some(replaced) = by_name.get(replaced.name()) {
+                graph.to_index(*replaced).to_string()
+            } else {
+                obsolete.insert(replaced.name());
+                format!("\"replaced-{}\"", escape_dot_string(replaced.name()))
+            };
+            writeln!(
+                write,
+                "    {} -> {target} [ color = darkorange, label = \"replaces\", fontcolor = darkorange ]",
+                graph.to_index(*node),
+            )?;
+        }
+    }
+    for name in obsolete {
+        writeln!(
+            write,
+            "    \"replaced-{}\" [ label = <<FONT FACE=\"{}\"><S>{name}</S></FONT>>, style = dashed, color = darkorange ]",
+            escape_dot_string(name),
+            style.font_face()
+        )?;
+    }
+    Ok(())
+}
+
+/// Get all packages in `graph` which belong to the collapsed repository.
+#[allow(
+    clippy::mutable_key_type,
+    reason = "We do not mutate the package pointer while traversing the graph"
+)]
+fn collapsed_packages<'a, G>(graph: G, style: &DotStyle<'_>) -> HashSet<PackageNode<'a>>
+where
+    G: Data<NodeWeight = PackageNode<'a>> + IntoNodeReferences,
+{
+    style
+        .collapse_repository
+        .map_or_else(HashSet::new, |repository| {
+            graph
+                .node_references()
+                .map(|node| *node.weight())
+                .filter(|node| {
+                    style
+                        .repositories
+                        .find_syncdb(node)
+                        .map_or("foreign", Db::name)
+                        == repository
+                })
+                .collect()
+        })
+}
+
+/// Print graph-level attributes of `style`.
+fn print_graph_attributes<W: Write>(write: &mut W, style: &DotStyle<'_>) -> std::io::Result<()> {
+    if let Some(color) = style.background {
+        writeln!(write, "    bgcolor = \"{}\"", escape_dot_string(color))?;
+    }
+    if let Some(font) = style.font {
+        let font = escape_dot_string(font);
+        writeln!(write, "    fontname = \"{font}\"")?;
+        writeln!(write, "    node [ fontname = \"{font}\" ]")?;
+        writeln!(write, "    edge [ fontname = \"{font}\" ]")?;
+    }
+    Ok(())
+}
+
+fn print_dot<'a, G, W: Write>(write: &mut W, graph: G, style: &DotStyle<'_>) -> std::io::Result<()>
 where
     G: GraphProp
+        + GraphBase<NodeId = PackageNode<'a>>
         + Data<NodeWeight = PackageNode<'a>, EdgeWeight = DependencyEdge>
         + IntoEdgeReferences
         + IntoNodeReferences
         + NodeIndexable,
 {
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let collapsed = collapsed_packages(graph, style);
+    // Dependencies on provided names, which we route through virtual nodes
+    // instead of printing them as direct edges.  Leave dependencies into or
+    // out of the collapsed repository to the repository node.
+    let provided_edges = if style.virtual_nodes {
+        provided_edges(graph)
+            .into_iter()
+            .filter(|(from, _, to, _)| !collapsed.contains(from) && !collapsed.contains(to))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let routed_edges = provided_edges
+        .iter()
+        .map(|(from, _, to, _)| (*from, *to))
+        .collect::<HashSet<_>>();
+    let direct_graph = EdgeFiltered::from_fn(graph, |edge: G::EdgeRef| {
+        !routed_edges.contains(&(edge.source(), edge.target()))
+    });
+    let visible_graph = NodeFiltered::from_fn(&direct_graph, |node| !collapsed.contains(&node));
+    #[allow(
+        clippy::mutable_key_type,
+        reason = "We do not mutate the package pointer while traversing the graph"
+    )]
+    let mut out_degrees = HashMap::new();
+    if style.show_degree {
+        for edge in graph.edge_references() {
+            *out_degrees.entry(edge.source()).or_insert(0) += 1;
+        }
+    }
+
     let get_node_attributes = |_graph, node: G::NodeRef| {
         let package = node.weight();
-        match format {
-            PrintOneLine::NameOnly => format!(
-                "label = <<FONT FACE=\"sans-serif\">{}</FONT>>",
-                package.name()
-            ),
-            PrintOneLine::WithVersion => format!(
-                "label = <<FONT FACE=\"sans-serif\"><B>{name} <FONT COLOR=\"green\">{version}</FONT></B></FONT>>",
-                name = package.name(),
-                version = package.version()
-            ),
+        let degree = style
+            .show_degree
+            .then(|| out_degrees.get(package).copied().unwrap_or_default());
+        let mut attributes = vec![node_label(package.package(), degree, style)];
+        if style.repository_classes {
+            let repository = style
+                .repositories
+                .find_syncdb(package)
+                .map_or("foreign", Db::name);
+            attributes.push(format!(
+                "class = \"repo-{}\"",
+                escape_dot_string(repository)
+            ));
+        }
+        if style.links
+            && let Some(url) = style.repositories.package_url(package)
+        {
+            attributes.push(format!("URL = \"{}\"", escape_dot_string(&url)));
+        }
+        if style.shape_by_reason {
+            attributes.push(match package.reason() {
+                PackageReason::Explicit => "shape = box".to_string(),
+                PackageReason::Depend => "shape = ellipse".to_string(),
+            });
+        }
+        attributes.join(", ")
+    };
+    let get_edge_attributes = |_graph, edge: G::EdgeRef| {
+        let attributes = edge_attributes(*edge.weight(), style);
+        if style.merge_optional_required
+            && *edge.weight() == DependencyEdge::Required
+            && is_required_and_optional(edge.source().package(), edge.target().package())
+        {
+            format!("{attributes}, color = orange")
+        } else {
+            attributes
         }
     };
     let dot = Dot::with_attr_getters(
-        graph,
+        &visible_graph,
         &[
             Config::EdgeNoLabel,
             Config::NodeNoLabel,
+            Config::GraphContentOnly,
             Config::RankDir(RankDir::TB),
         ],
-        &|_graph, edge| match *edge.weight() {
-            DependencyEdge::Required => "style = solid".to_string(),
-            DependencyEdge::Optional => "style = dashed".to_string(),
-        },
+        &get_edge_attributes,
         &get_node_attributes,
     );
-    writeln!(write, "{dot}")
+    let strict = if style.strict { "strict " } else { "" };
+    writeln!(write, "{strict}digraph {{")?;
+    print_graph_attributes(write, style)?;
+    write!(write, "{dot}")?;
+    print_virtual_nodes(write, graph, provided_edges, style)?;
+    if style.replaces {
+        print_replaces(write, graph, &collapsed, style)?;
+    }
+    if let Some(repository) = style.collapse_repository {
+        print_collapsed_repository(write, graph, repository, &collapsed, style)?;
+    }
+    writeln!(write, "}}")
 }